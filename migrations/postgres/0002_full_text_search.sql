@@ -0,0 +1,49 @@
+CREATE TABLE IF NOT EXISTS post_search (
+	msgid BIGINT NOT NULL,
+	submsg_id BIGINT NOT NULL,
+	board TEXT NOT NULL,
+	time BIGINT NOT NULL,
+	author TEXT NOT NULL,
+	body TEXT NOT NULL,
+	body_vector TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', body)) STORED
+);
+CREATE INDEX IF NOT EXISTS post_search_vector_idx ON post_search USING GIN (body_vector);
+CREATE INDEX IF NOT EXISTS post_search_post_idx ON post_search (msgid, submsg_id);
+CREATE OR REPLACE FUNCTION messages_search_sync() RETURNS TRIGGER AS $$
+BEGIN
+	IF TG_OP = 'INSERT' THEN
+		INSERT INTO post_search(msgid, submsg_id, board, time, author, body)
+			VALUES (NEW.msgid, 0, NEW.board, NEW.time, NEW.author, NEW.msg);
+	ELSIF TG_OP = 'UPDATE' THEN
+		UPDATE post_search SET body = NEW.msg, board = NEW.board
+			WHERE msgid = OLD.msgid AND submsg_id = 0;
+	ELSE
+		DELETE FROM post_search WHERE msgid = OLD.msgid;
+	END IF;
+	RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+CREATE OR REPLACE FUNCTION submessages_search_sync() RETURNS TRIGGER AS $$
+BEGIN
+	IF TG_OP = 'INSERT' THEN
+		INSERT INTO post_search(msgid, submsg_id, board, time, author, body)
+			VALUES (NEW.parent_msg, NEW.submsg_id, NEW.board, NEW.time, NEW.author, NEW.submsg);
+	ELSIF TG_OP = 'UPDATE' THEN
+		UPDATE post_search SET body = NEW.submsg, board = NEW.board
+			WHERE msgid = OLD.parent_msg AND submsg_id = OLD.submsg_id;
+	ELSE
+		DELETE FROM post_search WHERE msgid = OLD.parent_msg AND submsg_id = OLD.submsg_id;
+	END IF;
+	RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+DROP TRIGGER IF EXISTS messages_search_sync ON messages;
+CREATE TRIGGER messages_search_sync AFTER INSERT OR DELETE OR UPDATE OF msg, board ON messages
+	FOR EACH ROW EXECUTE FUNCTION messages_search_sync();
+DROP TRIGGER IF EXISTS submessages_search_sync ON submessages;
+CREATE TRIGGER submessages_search_sync AFTER INSERT OR DELETE OR UPDATE OF submsg, board ON submessages
+	FOR EACH ROW EXECUTE FUNCTION submessages_search_sync();
+INSERT INTO post_search(msgid, submsg_id, board, time, author, body)
+	SELECT msgid, 0, board, time, author, msg FROM messages;
+INSERT INTO post_search(msgid, submsg_id, board, time, author, body)
+	SELECT parent_msg, submsg_id, board, time, author, submsg FROM submessages;
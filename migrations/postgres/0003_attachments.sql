@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS attachments (
+	attachment_id BIGSERIAL PRIMARY KEY,
+	msgid BIGINT NOT NULL,
+	submsg_id BIGINT NOT NULL,
+	position BIGINT NOT NULL,
+	stored_name TEXT NOT NULL,
+	original_name TEXT NOT NULL,
+	mime_type TEXT NOT NULL,
+	byte_size BIGINT NOT NULL,
+	width BIGINT,
+	height BIGINT,
+	content_hash TEXT NOT NULL,
+	CONSTRAINT bind_msg
+		FOREIGN KEY(msgid)
+			REFERENCES messages(msgid)
+			ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS attachments_post_idx ON attachments(msgid, submsg_id);
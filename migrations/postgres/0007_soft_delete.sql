@@ -0,0 +1,4 @@
+ALTER TABLE messages ADD COLUMN deleted_at BIGINT;
+ALTER TABLE submessages ADD COLUMN deleted_at BIGINT;
+CREATE INDEX IF NOT EXISTS messages_deleted_idx ON messages(deleted_at);
+CREATE INDEX IF NOT EXISTS submessages_deleted_idx ON submessages(deleted_at);
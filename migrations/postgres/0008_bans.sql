@@ -0,0 +1,14 @@
+ALTER TABLE messages ADD COLUMN poster_hash TEXT NOT NULL DEFAULT '';
+ALTER TABLE submessages ADD COLUMN poster_hash TEXT NOT NULL DEFAULT '';
+CREATE INDEX IF NOT EXISTS messages_poster_idx ON messages(poster_hash);
+CREATE INDEX IF NOT EXISTS submessages_poster_idx ON submessages(poster_hash);
+CREATE TABLE IF NOT EXISTS bans (
+	ban_id BIGSERIAL PRIMARY KEY,
+	ip_hash TEXT NOT NULL DEFAULT '',
+	ip_range TEXT NOT NULL DEFAULT '',
+	board TEXT NOT NULL DEFAULT '',
+	reason TEXT NOT NULL,
+	created_at BIGINT NOT NULL,
+	expires_at BIGINT
+);
+CREATE INDEX IF NOT EXISTS bans_ip_hash_idx ON bans(ip_hash);
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS reports (
+	report_id BIGSERIAL PRIMARY KEY,
+	msgid BIGINT NOT NULL,
+	submsg_id BIGINT NOT NULL,
+	reason TEXT NOT NULL,
+	comment TEXT NOT NULL DEFAULT '',
+	reporter_hash TEXT NOT NULL DEFAULT '',
+	time BIGINT NOT NULL,
+	CONSTRAINT bind_msg
+		FOREIGN KEY(msgid)
+			REFERENCES messages(msgid)
+			ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS reports_post_idx ON reports(msgid, submsg_id);
+INSERT INTO reports(msgid, submsg_id, reason, time)
+	SELECT msgid, CASE WHEN msg_type = 'submsg' THEN COALESCE(submsg_index, 0) ELSE 0 END, 'Unspecified', CAST(EXTRACT(EPOCH FROM NOW()) AS BIGINT)
+	FROM flagged_messages
+	WHERE msgid IN (SELECT msgid FROM messages);
+DROP TABLE flagged_messages;
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS staff (
+	staff_id BIGSERIAL PRIMARY KEY,
+	username TEXT NOT NULL UNIQUE,
+	password_hash TEXT NOT NULL,
+	role TEXT NOT NULL,
+	boards TEXT NOT NULL DEFAULT '',
+	created_at BIGINT NOT NULL
+);
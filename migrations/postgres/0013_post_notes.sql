@@ -0,0 +1,15 @@
+ALTER TABLE messages ADD COLUMN mod_notice TEXT NOT NULL DEFAULT '';
+ALTER TABLE submessages ADD COLUMN mod_notice TEXT NOT NULL DEFAULT '';
+CREATE TABLE IF NOT EXISTS staff_notes (
+	note_id BIGSERIAL PRIMARY KEY,
+	msgid BIGINT NOT NULL,
+	submsg_id BIGINT NOT NULL,
+	time BIGINT NOT NULL,
+	author TEXT NOT NULL,
+	note TEXT NOT NULL,
+	CONSTRAINT bind_msg
+		FOREIGN KEY(msgid)
+			REFERENCES messages(msgid)
+			ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS staff_notes_post_idx ON staff_notes(msgid, submsg_id);
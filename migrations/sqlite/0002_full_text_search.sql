@@ -0,0 +1,35 @@
+CREATE VIRTUAL TABLE IF NOT EXISTS post_search USING fts5(
+	body,
+	board UNINDEXED,
+	msgid UNINDEXED,
+	submsg_id UNINDEXED,
+	time UNINDEXED,
+	author UNINDEXED,
+	tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TRIGGER IF NOT EXISTS messages_search_insert AFTER INSERT ON messages BEGIN
+	INSERT INTO post_search(body, board, msgid, submsg_id, time, author)
+		VALUES (new.msg, new.board, new.msgid, 0, new.time, new.author);
+END;
+CREATE TRIGGER IF NOT EXISTS messages_search_update AFTER UPDATE OF msg, board ON messages BEGIN
+	UPDATE post_search SET body = new.msg, board = new.board
+		WHERE msgid = old.msgid AND submsg_id = 0;
+END;
+CREATE TRIGGER IF NOT EXISTS messages_search_delete AFTER DELETE ON messages BEGIN
+	DELETE FROM post_search WHERE msgid = old.msgid;
+END;
+CREATE TRIGGER IF NOT EXISTS submessages_search_insert AFTER INSERT ON submessages BEGIN
+	INSERT INTO post_search(body, board, msgid, submsg_id, time, author)
+		VALUES (new.submsg, new.board, new.parent_msg, new.submsg_id, new.time, new.author);
+END;
+CREATE TRIGGER IF NOT EXISTS submessages_search_update AFTER UPDATE OF submsg, board ON submessages BEGIN
+	UPDATE post_search SET body = new.submsg, board = new.board
+		WHERE msgid = old.parent_msg AND submsg_id = old.submsg_id;
+END;
+CREATE TRIGGER IF NOT EXISTS submessages_search_delete AFTER DELETE ON submessages BEGIN
+	DELETE FROM post_search WHERE msgid = old.parent_msg AND submsg_id = old.submsg_id;
+END;
+INSERT INTO post_search(body, board, msgid, submsg_id, time, author)
+	SELECT msg, board, msgid, 0, time, author FROM messages;
+INSERT INTO post_search(body, board, msgid, submsg_id, time, author)
+	SELECT submsg, board, parent_msg, submsg_id, time, author FROM submessages;
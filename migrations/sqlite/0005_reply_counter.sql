@@ -0,0 +1,4 @@
+ALTER TABLE messages ADD COLUMN last_submsg_id BIGINT NOT NULL DEFAULT 0;
+UPDATE messages SET last_submsg_id = COALESCE(
+	(SELECT MAX(submsg_id) FROM submessages WHERE submessages.parent_msg = messages.msgid), 0
+);
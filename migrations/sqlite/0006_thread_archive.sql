@@ -0,0 +1,2 @@
+ALTER TABLE messages ADD COLUMN archived_at BIGINT;
+CREATE INDEX IF NOT EXISTS messages_board_archived_idx ON messages(board, archived_at);
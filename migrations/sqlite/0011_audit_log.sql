@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS audit_log (
+	entry_id INTEGER PRIMARY KEY,
+	time BIGINT NOT NULL,
+	actor TEXT NOT NULL,
+	action TEXT NOT NULL,
+	board TEXT NOT NULL DEFAULT '',
+	msgid BIGINT NOT NULL DEFAULT 0,
+	submsg_id BIGINT NOT NULL DEFAULT 0,
+	target TEXT NOT NULL DEFAULT '',
+	snapshot TEXT NOT NULL DEFAULT '',
+	reason TEXT NOT NULL DEFAULT ''
+);
+CREATE INDEX IF NOT EXISTS audit_log_actor_idx ON audit_log(actor);
+CREATE INDEX IF NOT EXISTS audit_log_post_idx ON audit_log(msgid, submsg_id);
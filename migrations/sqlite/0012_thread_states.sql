@@ -0,0 +1,2 @@
+ALTER TABLE messages ADD COLUMN stickied_at BIGINT;
+ALTER TABLE messages ADD COLUMN locked_at BIGINT;
@@ -0,0 +1,7 @@
+ALTER TABLE messages ADD COLUMN held_at BIGINT;
+ALTER TABLE messages ADD COLUMN shadowed_at BIGINT;
+ALTER TABLE submessages ADD COLUMN held_at BIGINT;
+ALTER TABLE submessages ADD COLUMN shadowed_at BIGINT;
+ALTER TABLE bans ADD COLUMN shadow BIGINT NOT NULL DEFAULT 0;
+CREATE INDEX IF NOT EXISTS messages_held_idx ON messages(held_at);
+CREATE INDEX IF NOT EXISTS submessages_held_idx ON submessages(held_at);
@@ -0,0 +1,2 @@
+ALTER TABLE messages ADD COLUMN tripcode TEXT NOT NULL DEFAULT '';
+ALTER TABLE submessages ADD COLUMN tripcode TEXT NOT NULL DEFAULT '';
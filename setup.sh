@@ -21,11 +21,36 @@ if [ ! -f "./data/banlist.yaml" ]; then
 	echo 'Creating empty banword list'
 	echo '---
 # This is a global list of banned words in YAML format.
-# You can enter actual words or Regex wrapped in single quotes.
-# Format the file as a YAML list.
+# You can enter actual words or Regex wrapped in single quotes (matching is case-insensitive).
+# Format the file as a YAML list. Plain entries are checked against author names and messages
+# on every board; entries can also be limited to some parts of posts and some boards:
+# - pattern: '\''buy\s+now'\''
+#   scope: [author, message, filename]
+#   boards: [b]
+# The list can also be edited in the admin dashboard. Changes are applied without a restart.
 ' > ./data/banlist.yaml
 fi
 
+if [ ! -f "./data/wordfilters.yaml" ]; then
+	echo 'Creating empty wordfilter list'
+	echo '---
+# Wordfilters rewrite text instead of rejecting the post. Rules are regex (case-insensitive,
+# wrap them in single quotes) applied in the order they are listed; replacements can use
+# capture groups like $1 or ${name}. Store rules change author names and messages that passed
+# the banlist before they are saved, render rules change formatted message HTML (and search
+# snippets) every time it is shown.
+# - pattern: '\''\bcloud(s?)\b'\''
+#   replacement: '\''butt$1'\''
+#   stage: store          # store (default) or render
+#   scope: [author, message]
+#   boards: [b]           # empty or missing for all boards
+# - pattern: '\''\bFAQ\b'\''
+#   replacement: '\''<a href="/faq">FAQ</a>'\''
+#   stage: render
+# Changes are applied without a restart.
+' > ./data/wordfilters.yaml
+fi
+
 # if not running in container, prompt for dashboard password
 if [ -z "${acsim_compose}" ] && [ -z "${acsim_docker}" ]; then
 	echo -n "Enter the password that will be used for admin dashboard: "
@@ -34,7 +59,15 @@ else
 	acsim_pass=$(cat /dev/random | head -c 20 | sha256sum | head -c 8 | xargs)
 fi
 
-passhashed=$(echo -n $acsim_pass | sha256sum | head -c 64 | xargs)
+# the password is hashed with Argon2id by ACSIM itself: the installed binary in Docker images,
+# a fresh build otherwise
+if [ -x ./acsim ]; then
+	passhashed=$(echo -n "$acsim_pass" | ./acsim hash-password)
+else
+	passhashed=$(echo -n "$acsim_pass" | cargo run -q -- hash-password)
+fi
+ipsalt=$(cat /dev/urandom | head -c 32 | sha256sum | head -c 64 | xargs)
+tripsalt=$(cat /dev/urandom | head -c 32 | sha256sum | head -c 64 | xargs)
 
 if [ ! -f "./data/config.yaml" ]; then
 	echo 'Creating default config file for server'
@@ -70,8 +103,28 @@ captcha_num_limit: 200
 # Displays log level at the start of each log line
 display_log_level: true
 
-# Password for admin dashboard, stored as a SHA-256 hash
-admin_password: $passhashed
+# Password of the initial \`admin\` staff account, stored as an Argon2id hash (\`acsim hash-password\` creates one).
+# It's only used to create that account; other staff accounts are managed in the dashboard
+admin_password: '$passhashed'
+
+# Failed dashboard logins allowed from one address before it's locked out for a number of minutes (0 disables the limit)
+login_max_attempts: 5
+login_lockout_minutes: 15
+
+# SameSite attribute of the dashboard session cookie (strict, lax or none) and whether it's only sent over HTTPS.
+# If session_cookie_secure is omitted, it follows use_https
+session_cookie_same_site: strict
+
+# Secret mixed into hashes of poster IP addresses. Keep it private and don't change it,
+# otherwise existing bans of single addresses stop working
+ip_hash_salt: $ipsalt
+
+# Secret mixed into secure tripcodes (author names like 'name##password'). Keep it private and
+# don't change it, otherwise every secure tripcode changes
+tripcode_salt: $tripsalt
+
+# Reasons users can choose from when reporting posts
+report_reasons: [Spam, Illegal content, Off-topic, Other]
 
 # Name of the imageboard displayed to users
 site_name: ACSIM
@@ -79,7 +132,71 @@ site_name: ACSIM
 # Frontend used by the imageboard
 site_frontend: acsim_ungapped
 
-# Boards served to users. Consists of board designation and short description
+# Apply pending database migrations on startup. If disabled, run \`acsim migrate\` manually after upgrading
+auto_migrate: true
+
+# Max number of simultaneous database connections and time (in seconds) to wait for a free one
+db_max_connections: 10
+db_acquire_timeout: 30
+
+# SQLite only: time (in milliseconds) to wait for a locked database and write-ahead logging toggle
+sqlite_busy_timeout: 5000
+sqlite_wal: true
+
+# Deleted posts are kept in the dashboard trash for this many days before being removed for good
+trash_retention_days: 7
+
+# Max length (in bytes) of messages and author names
+max_message_length: 4000
+max_author_length: 250
+
+# Max number of files attached to one post and MIME types that can be attached
+max_attachments: 5
+allowed_media_types: [image/png, image/jpeg, image/gif, image/webp, image/bmp, video/mp4, video/webm]
+
+# Name displayed for posts sent without one
+default_poster_name: Anonymous
+
+# Don't accept new threads without media files
+require_image: false
+
+# Posts held for approval in the dashboard before they are shown: 'off', 'threads' (new threads),
+# 'all' or 'first_posts' (first moderation_first_posts posts of every poster on a board)
+moderation: off
+moderation_first_posts: 1
+
+# Anti-spam limits. Every poster has to wait thread_cooldown_secs between their threads and
+# reply_cooldown_secs between their replies on a board (0 disables the cooldown)
+thread_cooldown_secs: 60
+reply_cooldown_secs: 10
+# Max number of links to other websites in one post
+max_links: 5
+# Posts very similar to another post on the same board from the last duplicate_window_secs
+# are refused (0 disables the check). Similarity is a number from 0 to 1
+duplicate_window_secs: 600
+duplicate_similarity: 0.9
+# Once a board receives flood_limit posts within flood_window_secs, new posts are refused until
+# the rate goes down (0 disables the limit)
+flood_limit: 0
+flood_window_secs: 60
+
+# Boards served to users. Consists of board designation and short description.
+# Instead of a description, a board can be given a map of settings:
+#    a:
+#        description: Anime
+#        limit_policy: archive      # 'delete' (default) or 'archive' threads pushed off the board
+#        archive_retention_days: 30 # 0 keeps archived threads forever
+#        archive_limit: 500         # 0 means no limit on archived threads
+# Board settings can also override bumplimit, hard_limit, page_limit, max_message_length,
+# max_author_length, max_attachments, allowed_media_types, default_poster_name, require_image,
+# moderation, moderation_first_posts and anti-spam limits:
+#    p:
+#        description: Photography
+#        require_image: true
+#        moderation: first_posts
+#        allowed_media_types: [image/png, image/jpeg]
+#        default_poster_name: Photographer
+#        flood_limit: 30
 boards:
     b: Random
 
@@ -95,14 +212,12 @@ if [ "$1" = "POSTGRES" ]; then
 		echo "Please specify username for Postgres DB"
 		exit
 	fi
-	# if not in Compose, create the db and tables
+	# if not in Compose, create the db (tables are created by ACSIM migrations)
 	if [ -z "${acsim_compose}" ]; then
 		echo 'Creating database'
 		createdb -U $2 acsim_db
-		echo 'Creating table scheme'
-		echo ./pg_init.sql | psql -U $2 -d acsim_db;
 		pg_connect_string="DATABASE_URL=\"postgres://$2@localhost:5432/acsim_db\""
-	# otherwise, leave db/table creation to Postgres image
+	# otherwise, leave db creation to Postgres image
 	else
 		echo 'Database setup rests on Compose image'
 		pg_connect_string="DATABASE_URL=\"postgres://postgres:generic@db:5432/acsim_db\""
@@ -111,11 +226,9 @@ if [ "$1" = "POSTGRES" ]; then
 	echo $pg_connect_string > .env
 
 elif [ "$1" = "SQLITE" ]; then
-	echo "Creating database"
-	echo "Creating table scheme" # same thing here
-	sqlite3 ./data/acsim.db < ./sqlite_init.sql
+	# database file and tables are created by ACSIM on first launch
 	echo "Writing database URL to .env"
-	echo 'DATABASE_URL="sqlite://data/acsim.db"' > .env
+	echo 'DATABASE_URL="sqlite://data/acsim.db?mode=rwc"' > .env
 else
 	echo "Please specify database type (POSTGRES or SQLITE) in script args"
 	exit
@@ -0,0 +1,160 @@
+//! Anti-spam checks applied to new posts: posting cooldowns of every poster, link limits,
+//! near-duplicate detection and board-wide flood limits. Limits are set per board in config.yaml.
+
+use crate::db_control::DatabaseWrapper;
+use crate::routes::Rejection;
+use crate::EffectiveBoardSettings;
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+/// Messages shorter than this (after normalization) are never treated as near-duplicates,
+/// so that short replies like "thanks" can be posted by everyone
+const MIN_DUPLICATE_LENGTH: usize = 16;
+
+/// Start of a link to an external website
+static LINK_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)https?://").unwrap());
+
+/// Runs anti-spam checks of a new thread or reply. Returns the reason the post should be refused, if any
+pub async fn check_post(
+    client: &DatabaseWrapper,
+    settings: &EffectiveBoardSettings,
+    board: &str,
+    poster_hash: &str,
+    new_thread: bool,
+    message: &str,
+) -> Result<Option<Rejection>, sqlx::Error> {
+    let now = crate::html_proc::since_epoch();
+
+    // board-wide circuit breaker, tripped while the board receives too many posts
+    if settings.flood_limit > 0
+        && client
+            .get_posting_rate(board, settings.flood_window_secs.into())
+            .await?
+            >= settings.flood_limit.into()
+    {
+        return Ok(Some(Rejection::BoardFlooded));
+    }
+
+    let (cooldown, rejection) = match new_thread {
+        true => (settings.thread_cooldown_secs, Rejection::ThreadCooldown),
+        false => (settings.reply_cooldown_secs, Rejection::ReplyCooldown),
+    };
+    if cooldown > 0 {
+        if let Some(last) = client
+            .get_last_post_time(poster_hash, board, new_thread)
+            .await?
+        {
+            if now - last < cooldown.into() {
+                return Ok(Some(rejection));
+            }
+        }
+    }
+
+    if count_links(message) > settings.max_links {
+        return Ok(Some(Rejection::TooManyLinks));
+    }
+
+    if settings.duplicate_window_secs > 0 {
+        let normalized = normalize(message);
+        if is_comparable(&normalized) {
+            let since = now - i64::from(settings.duplicate_window_secs);
+            let recent = client.get_recent_post_texts(board, since).await?;
+            if recent.iter().any(|text| {
+                similarity(&normalized, &normalize(text)) >= settings.duplicate_similarity
+            }) {
+                return Ok(Some(Rejection::DuplicatePost));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Counts links to external websites in a message
+fn count_links(message: &str) -> usize {
+    LINK_REGEX.find_iter(message).count()
+}
+
+/// Lowercases text and replaces everything but letters and digits with single spaces,
+/// so that posts differing only in case, punctuation or spacing are compared as equal
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Checks if a normalized message is long enough to be compared with recent posts
+fn is_comparable(normalized: &str) -> bool {
+    normalized.chars().count() >= MIN_DUPLICATE_LENGTH
+}
+
+/// Jaccard similarity (from 0 to 1) of character trigrams of two normalized texts
+fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let trigrams = |text: &str| -> HashSet<[char; 3]> {
+        let chars: Vec<char> = text.chars().collect();
+        chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+    };
+    let (a, b) = (trigrams(a), trigrams(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_texts_are_equal() {
+        let text = normalize("Buy cheap watches at my shop today");
+        assert_eq!(similarity(&text, &text), 1.0);
+        assert_eq!(
+            similarity(&text, &normalize("BUY cheap   watches, at my shop today!!")),
+            1.0
+        );
+    }
+
+    #[test]
+    fn disjoint_texts_share_nothing() {
+        let a = normalize("aaaaaaaaaaaaaaaaaaaa");
+        let b = normalize("bbbbbbbbbbbbbbbbbbbb");
+        assert_eq!(similarity(&a, &b), 0.0);
+        assert_eq!(similarity("", "ab"), 0.0);
+    }
+
+    #[test]
+    fn near_duplicates_are_similar() {
+        let a = normalize("Buy cheap watches at my shop today");
+        let b = normalize("Buy cheap watches at my shop tomorrow");
+        let c = normalize("What do you think of the new season?");
+        assert!(similarity(&a, &b) > 0.7);
+        assert!(similarity(&a, &c) < 0.2);
+    }
+
+    #[test]
+    fn short_messages_are_not_compared() {
+        let short = "x".repeat(MIN_DUPLICATE_LENGTH - 1);
+        let long = "x".repeat(MIN_DUPLICATE_LENGTH);
+        assert!(!is_comparable(&short));
+        assert!(is_comparable(&long));
+        // punctuation and spacing don't count towards the length
+        assert!(!is_comparable(&normalize("thanks!!!!!!!!!!!!!!!!!!!!")));
+    }
+
+    #[test]
+    fn links_are_counted() {
+        assert_eq!(count_links("no links here"), 0);
+        assert_eq!(
+            count_links("see http://a.example and HTTPS://b.example/x"),
+            2
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! List of banned words and patterns, stored in `data/banlist.yaml`.
+//! The list is compiled into a single `RegexSet` once and recompiled only when the file changes;
+//! changes that don't compile are rejected and the previous list stays in use.
+
+use crate::rule_file::{default_fields, PostField, RuleFile, RuleFileError, WatchedFile};
+use regex::{RegexSet, RegexSetBuilder};
+use serde::{Deserialize, Serialize};
+
+/// Default location of the banlist
+pub const BANLIST_PATH: &str = "./data/banlist.yaml";
+
+/// Comment written at the start of the banlist file when it is saved from the dashboard
+const BANLIST_HEADER: &str =
+    "# List of banned words in YAML format, managed from the admin dashboard.
+# Entries are either plain patterns (checked against author names and messages on every board)
+# or maps with `pattern`, `scope` (any of author, message, filename) and `boards` (empty for all).
+# Patterns are case-insensitive regex; wrap them in single quotes.
+";
+
+/// Single banned pattern. Empty `boards` means that it applies to every board
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(from = "RawBanlistEntry")]
+pub struct BanlistEntry {
+    pub pattern: String,
+    pub scope: Vec<PostField>,
+    pub boards: Vec<String>,
+}
+
+/// Raw banlist entry, as written in banlist.yaml
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawBanlistEntry {
+    Pattern(String),
+    Detailed {
+        pattern: String,
+        #[serde(default = "default_fields")]
+        scope: Vec<PostField>,
+        #[serde(default)]
+        boards: Vec<String>,
+    },
+}
+
+impl From<RawBanlistEntry> for BanlistEntry {
+    fn from(entry: RawBanlistEntry) -> Self {
+        match entry {
+            RawBanlistEntry::Pattern(pattern) => BanlistEntry {
+                pattern,
+                scope: default_fields(),
+                boards: Vec::new(),
+            },
+            RawBanlistEntry::Detailed {
+                pattern,
+                scope,
+                boards,
+            } => BanlistEntry {
+                pattern,
+                scope,
+                boards,
+            },
+        }
+    }
+}
+
+impl BanlistEntry {
+    fn applies_to(&self, board: &str, scope: PostField) -> bool {
+        self.scope.contains(&scope)
+            && (self.boards.is_empty() || self.boards.iter().any(|b| b == board))
+    }
+}
+
+/// Banlist entries along with their compiled patterns
+pub struct Banlist {
+    pub entries: Vec<BanlistEntry>,
+    set: RegexSet,
+}
+
+impl RuleFile for Banlist {
+    type Rule = BanlistEntry;
+
+    const NAME: &'static str = "banlist";
+
+    fn compile(entries: Vec<BanlistEntry>) -> Result<Banlist, regex::Error> {
+        let set = RegexSetBuilder::new(entries.iter().map(|e| &e.pattern))
+            .case_insensitive(true)
+            .build()?;
+        Ok(Banlist { entries, set })
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+impl Banlist {
+    /// Returns the first entry matching `text` that applies to a board and part of a post
+    pub fn find_match(&self, board: &str, scope: PostField, text: &str) -> Option<&BanlistEntry> {
+        self.set
+            .matches(text)
+            .into_iter()
+            .map(|i| &self.entries[i])
+            .find(|e| e.applies_to(board, scope))
+    }
+}
+
+/// Banlist shared between request handlers, along with the file it was loaded from
+pub type SharedBanlist = WatchedFile<Banlist>;
+
+impl SharedBanlist {
+    /// Validates new banlist entries, writes them to the file and starts using them
+    pub fn save(&self, entries: Vec<BanlistEntry>) -> Result<(), RuleFileError> {
+        let banlist = Banlist::compile(entries)?;
+        let contents = format!(
+            "{}{}",
+            BANLIST_HEADER,
+            serde_yaml::to_string(&banlist.entries)?
+        );
+        self.replace(banlist, &contents)
+    }
+}
@@ -0,0 +1,445 @@
+//! Export and import of boards as portable archives.
+//! An archive is a gzipped tarball holding `manifest.json`, `threads.ndjson`
+//! (one thread with all of its replies per line) and referenced media files in `media/`.
+//! Its contents don't depend on the database backend.
+
+use crate::db_control::{
+    self, DatabaseWrapper, ImportedThread, NewAttachment, NewPost, PostVisibility,
+};
+use crate::routes::FileType;
+use crate::BoardConfig;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+
+const FORMAT_NAME: &str = "acsim-board-export";
+const FORMAT_VERSION: i64 = 1;
+
+/// Errors that can happen while exporting or importing boards
+#[derive(Debug)]
+pub enum TransferError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Database(sqlx::Error),
+    Format(String),
+}
+
+impl std::fmt::Display for TransferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferError::Io(e) => write!(f, "I/O error: {}", e),
+            TransferError::Json(e) => write!(f, "malformed JSON: {}", e),
+            TransferError::Database(e) => write!(f, "database error: {}", e),
+            TransferError::Format(e) => write!(f, "invalid archive: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for TransferError {
+    fn from(e: std::io::Error) -> Self {
+        TransferError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TransferError {
+    fn from(e: serde_json::Error) -> Self {
+        TransferError::Json(e)
+    }
+}
+
+impl From<sqlx::Error> for TransferError {
+    fn from(e: sqlx::Error) -> Self {
+        TransferError::Database(e)
+    }
+}
+
+/// Contents of `manifest.json`
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    format: String,
+    version: i64,
+    acsim_version: String,
+    exported_at: i64,
+    boards: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedAttachment {
+    stored_name: String,
+    original_name: String,
+    mime_type: String,
+    byte_size: i64,
+    width: Option<i64>,
+    height: Option<i64>,
+    content_hash: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedReply {
+    submsg_id: i64,
+    time: i64,
+    author: String,
+    #[serde(default)]
+    tripcode: String,
+    body: String,
+    attachments: Vec<ExportedAttachment>,
+}
+
+/// Single line of `threads.ndjson`
+#[derive(Serialize, Deserialize)]
+struct ExportedThread {
+    msgid: i64,
+    board: String,
+    time: i64,
+    author: String,
+    #[serde(default)]
+    tripcode: String,
+    body: String,
+    latest_submsg: i64,
+    last_submsg_id: i64,
+    archived_at: Option<i64>,
+    attachments: Vec<ExportedAttachment>,
+    replies: Vec<ExportedReply>,
+}
+
+/// Number of items moved by export or import
+pub struct TransferSummary {
+    pub threads: usize,
+    pub replies: usize,
+    pub media_files: usize,
+}
+
+fn export_attachments(rows: &[db_control::AttachmentRow]) -> Vec<ExportedAttachment> {
+    rows.iter()
+        .map(|a| ExportedAttachment {
+            stored_name: a.stored_name.clone(),
+            original_name: a.original_name.clone(),
+            mime_type: a.mime_type.clone(),
+            byte_size: a.byte_size,
+            width: a.width,
+            height: a.height,
+            content_hash: a.content_hash.clone(),
+        })
+        .collect()
+}
+
+/// Writes threads (including archived ones) of given boards into an archive at `path`.
+/// Deleted posts are left out.
+pub async fn export_boards(
+    client: &DatabaseWrapper,
+    boards: &[String],
+    path: &Path,
+) -> Result<TransferSummary, TransferError> {
+    let mut summary = TransferSummary {
+        threads: 0,
+        replies: 0,
+        media_files: 0,
+    };
+    let mut threads_ndjson = Vec::new();
+    let mut media = Vec::new();
+
+    for board in boards {
+        // posts held for approval or shadowed are left out
+        for message in client.get_all_messages(board).await? {
+            let replies = client.get_submessages(message.msgid, "").await?;
+            let thread = ExportedThread {
+                msgid: message.msgid,
+                board: message.board,
+                time: message.time,
+                author: message.author,
+                tripcode: message.tripcode,
+                body: message.msg,
+                latest_submsg: message.latest_submsg,
+                last_submsg_id: message.last_submsg_id,
+                archived_at: message.archived_at,
+                attachments: export_attachments(&message.attachments),
+                replies: replies
+                    .into_iter()
+                    .map(|r| ExportedReply {
+                        submsg_id: r.submsg_id,
+                        time: r.time,
+                        author: r.author,
+                        tripcode: r.tripcode,
+                        body: r.submsg,
+                        attachments: export_attachments(&r.attachments),
+                    })
+                    .collect(),
+            };
+            media.extend(thread.attachments.iter().map(|a| a.stored_name.clone()));
+            for reply in thread.replies.iter() {
+                media.extend(reply.attachments.iter().map(|a| a.stored_name.clone()));
+            }
+            summary.threads += 1;
+            summary.replies += thread.replies.len();
+            serde_json::to_writer(&mut threads_ndjson, &thread)?;
+            threads_ndjson.push(b'\n');
+        }
+    }
+
+    let manifest = serde_json::to_vec_pretty(&Manifest {
+        format: FORMAT_NAME.to_string(),
+        version: FORMAT_VERSION,
+        acsim_version: env!("CARGO_PKG_VERSION").to_string(),
+        exported_at: crate::html_proc::since_epoch(),
+        boards: boards.to_vec(),
+    })?;
+
+    let mut builder =
+        tar::Builder::new(GzEncoder::new(File::create(path)?, Compression::default()));
+    append_bytes(&mut builder, "manifest.json", &manifest)?;
+    append_bytes(&mut builder, "threads.ndjson", &threads_ndjson)?;
+
+    let mut seen = HashSet::new();
+    for stored_name in media {
+        if !seen.insert(stored_name.clone()) {
+            continue;
+        }
+        let media_path = format!("{}/{}", db_control::USER_IMAGES_DIR, stored_name);
+        match builder.append_path_with_name(&media_path, format!("media/{}", stored_name)) {
+            Ok(_) => summary.media_files += 1,
+            Err(e) => log::error!("Skipping media file {}: {}", media_path, e),
+        }
+    }
+    builder.into_inner()?.finish()?.flush()?;
+
+    Ok(summary)
+}
+
+fn append_bytes<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    data: &[u8],
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(crate::html_proc::since_epoch() as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)
+}
+
+/// Rewrites `>>msg` and `>>msg.sub` links to threads that got new IDs.
+/// Links to threads outside of the archive are left as they are.
+/// Returns `None` if nothing was changed.
+fn rewrite_links(text: &str, new_ids: &HashMap<i64, i64>) -> Option<String> {
+    let link = Regex::new(r">>(\d+)").unwrap();
+    let mut changed = false;
+    let result = link.replace_all(text, |caps: &regex::Captures| {
+        match caps[1].parse::<i64>().ok().and_then(|id| new_ids.get(&id)) {
+            Some(new_id) => {
+                changed = true;
+                format!(">>{}", new_id)
+            }
+            None => caps[0].to_string(),
+        }
+    });
+    if changed {
+        Some(result.to_string())
+    } else {
+        None
+    }
+}
+
+/// Reads an archive at `path` and adds its threads to the database under new IDs.
+/// Media files are copied into `USER_IMAGES_DIR` under new names.
+pub async fn import_archive(
+    client: &DatabaseWrapper,
+    config: &BoardConfig,
+    path: &Path,
+) -> Result<TransferSummary, TransferError> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+    let mut manifest: Option<Manifest> = None;
+    let mut threads_ndjson = String::new();
+    // old stored name -> new stored name
+    let mut media: HashMap<String, String> = HashMap::new();
+
+    let unpack_result = (|| -> Result<(), TransferError> {
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.to_string_lossy().to_string();
+            if entry_path == "manifest.json" {
+                manifest = Some(serde_json::from_reader(&mut entry)?);
+            } else if entry_path == "threads.ndjson" {
+                entry.read_to_string(&mut threads_ndjson)?;
+            } else if let Some(stored_name) = entry_path.strip_prefix("media/") {
+                // refusing anything that could escape the media directory
+                if stored_name.is_empty()
+                    || stored_name.contains(['/', '\\'])
+                    || stored_name.starts_with('.')
+                {
+                    return Err(TransferError::Format(format!(
+                        "bad media file name: {}",
+                        entry_path
+                    )));
+                }
+                // links and other special entries would be served as they are
+                if !entry.header().entry_type().is_file() {
+                    return Err(TransferError::Format(format!(
+                        "media entry is not a regular file: {}",
+                        entry_path
+                    )));
+                }
+                let new_name = match stored_name.rsplit_once('.') {
+                    Some((_, extension)) => format!("{}.{}", rand::random::<u64>(), extension),
+                    None => rand::random::<u64>().to_string(),
+                };
+                media.insert(stored_name.to_string(), new_name.clone());
+                // copied rather than unpacked, so that permissions and times of the entry are ignored
+                let mut file =
+                    File::create(format!("{}/{}", db_control::USER_IMAGES_DIR, new_name))?;
+                std::io::copy(&mut entry, &mut file)?;
+            }
+        }
+        Ok(())
+    })();
+
+    let result = match unpack_result {
+        Ok(_) => insert_threads(client, config, manifest, &threads_ndjson, &media).await,
+        Err(e) => Err(e),
+    };
+
+    if result.is_err() {
+        // files of a failed import aren't referenced by anything
+        for new_name in media.values() {
+            let _ = std::fs::remove_file(format!("{}/{}", db_control::USER_IMAGES_DIR, new_name));
+        }
+    }
+    result
+}
+
+async fn insert_threads(
+    client: &DatabaseWrapper,
+    config: &BoardConfig,
+    manifest: Option<Manifest>,
+    threads_ndjson: &str,
+    media: &HashMap<String, String>,
+) -> Result<TransferSummary, TransferError> {
+    match manifest {
+        Some(m) if m.format == FORMAT_NAME && m.version <= FORMAT_VERSION => {
+            log::info!(
+                "Importing boards {:?} exported by ACSIM v{}",
+                m.boards,
+                m.acsim_version
+            )
+        }
+        Some(m) => {
+            return Err(TransferError::Format(format!(
+                "unsupported format {} (version {})",
+                m.format, m.version
+            )))
+        }
+        None => return Err(TransferError::Format("missing manifest.json".to_string())),
+    };
+
+    let mut threads = Vec::new();
+    for line in threads_ndjson.as_bytes().lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            threads.push(serde_json::from_str::<ExportedThread>(&line)?);
+        }
+    }
+
+    // registering media files that made it into the archive
+    let import_attachments = |exported: &[ExportedAttachment]| -> Vec<NewAttachment> {
+        exported
+            .iter()
+            .filter_map(|a| {
+                let new_name = media.get(&a.stored_name)?;
+                let path = format!("{}/{}", db_control::USER_IMAGES_DIR, new_name);
+                match NewAttachment::from_file(Path::new(&path), &a.original_name) {
+                    Some(n) if FileType::from_mime(&n.mime_type) != FileType::Invalid => Some(n),
+                    _ => {
+                        log::error!("Skipping invalid media file: {}", a.stored_name);
+                        None
+                    }
+                }
+            })
+            .collect()
+    };
+    let attachments: Vec<(Vec<NewAttachment>, Vec<Vec<NewAttachment>>)> = threads
+        .iter()
+        .map(|t| {
+            (
+                import_attachments(&t.attachments),
+                t.replies
+                    .iter()
+                    .map(|r| import_attachments(&r.attachments))
+                    .collect(),
+            )
+        })
+        .collect();
+
+    let mut summary = TransferSummary {
+        threads: threads.len(),
+        replies: 0,
+        media_files: 0,
+    };
+    let mut imported = Vec::new();
+    for (thread, (thread_attachments, reply_attachments)) in threads.iter().zip(attachments.iter())
+    {
+        if !config.boards.contains_key(&thread.board) {
+            log::error!(
+                "Board /{}/ is not in config.yaml; its threads are imported, but won't be served",
+                thread.board
+            );
+        }
+        summary.replies += thread.replies.len();
+        summary.media_files +=
+            thread_attachments.len() + reply_attachments.iter().map(Vec::len).sum::<usize>();
+        imported.push(ImportedThread {
+            old_msgid: thread.msgid,
+            post: NewPost {
+                board: &thread.board,
+                time: thread.time,
+                author: &thread.author,
+                tripcode: &thread.tripcode,
+                body: &thread.body,
+                // poster identities are specific to an instance, so they are never exported
+                poster_hash: "",
+                attachments: thread_attachments,
+                visibility: PostVisibility::Public,
+            },
+            latest_submsg: thread.latest_submsg,
+            last_submsg_id: thread.last_submsg_id,
+            archived_at: thread.archived_at,
+            replies: thread
+                .replies
+                .iter()
+                .zip(reply_attachments.iter())
+                .map(|(r, a)| {
+                    (
+                        r.submsg_id,
+                        NewPost {
+                            board: &thread.board,
+                            time: r.time,
+                            author: &r.author,
+                            tripcode: &r.tripcode,
+                            body: &r.body,
+                            poster_hash: "",
+                            attachments: a,
+                            visibility: PostVisibility::Public,
+                        },
+                    )
+                })
+                .collect(),
+        });
+    }
+
+    client.import_threads(&imported, rewrite_links).await?;
+
+    // media files that ended up unused (invalid ones, for instance)
+    let used: HashSet<&String> = attachments
+        .iter()
+        .flat_map(|(t, r)| t.iter().chain(r.iter().flatten()))
+        .map(|a| &a.stored_name)
+        .collect();
+    for new_name in media.values().filter(|n| !used.contains(n)) {
+        let _ = std::fs::remove_file(format!("{}/{}", db_control::USER_IMAGES_DIR, new_name));
+    }
+
+    Ok(summary)
+}
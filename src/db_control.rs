@@ -1,7 +1,74 @@
 //! Contains struct used for handling connection and queries
 //! to PostgreSQL/SQLite database used by ACSIM
 
-use sqlx::{any::AnyPoolOptions, AnyPool, Row};
+use crate::BoardConfig;
+use notes::StaffNoteRow;
+use sqlx::{any::AnyPoolOptions, AnyConnection, AnyPool, Executor, Row, TypeInfo, ValueRef};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+pub mod antispam;
+pub mod approval;
+pub mod audit;
+pub mod bans;
+pub mod copy;
+pub mod migrations;
+pub mod notes;
+pub mod reports;
+pub mod staff;
+
+/// Database backends supported by ACSIM
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DatabaseKind {
+    Sqlite,
+    Postgres,
+}
+
+impl DatabaseKind {
+    /// Detects database backend from connection URL
+    pub fn from_url(url: &str) -> Option<DatabaseKind> {
+        if url.starts_with("sqlite:") {
+            Some(DatabaseKind::Sqlite)
+        } else if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+            Some(DatabaseKind::Postgres)
+        } else {
+            None
+        }
+    }
+}
+
+/// Nullable integer column. `Any` driver of sqlx 0.7 never reports values as NULL,
+/// so they can't be decoded into `Option<i64>` directly
+pub struct NullableInt(Option<i64>);
+
+impl sqlx::Type<sqlx::Any> for NullableInt {
+    fn type_info() -> sqlx::any::AnyTypeInfo {
+        <i64 as sqlx::Type<sqlx::Any>>::type_info()
+    }
+
+    fn compatible(ty: &sqlx::any::AnyTypeInfo) -> bool {
+        ty.name() == "NULL" || <i64 as sqlx::Type<sqlx::Any>>::compatible(ty)
+    }
+}
+
+impl<'r> sqlx::Decode<'r, sqlx::Any> for NullableInt {
+    fn decode(value: sqlx::any::AnyValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        if value.type_info().name() == "NULL" {
+            Ok(NullableInt(None))
+        } else {
+            Ok(NullableInt(Some(<i64 as sqlx::Decode<sqlx::Any>>::decode(
+                value,
+            )?)))
+        }
+    }
+}
+
+impl From<NullableInt> for Option<i64> {
+    fn from(value: NullableInt) -> Self {
+        value.0
+    }
+}
 
 /// Deserialized DB row containing a message (thread)
 #[derive(Debug, sqlx::FromRow)]
@@ -10,9 +77,28 @@ pub struct MessageRow {
     pub board: String,
     pub time: i64,
     pub author: String,
+    pub tripcode: String,
     pub msg: String,
-    pub image: String,
     pub latest_submsg: i64,
+    pub last_submsg_id: i64,
+    #[sqlx(try_from = "NullableInt")]
+    pub archived_at: Option<i64>,
+    #[sqlx(try_from = "NullableInt")]
+    pub deleted_at: Option<i64>,
+    pub poster_hash: String,
+    #[sqlx(try_from = "NullableInt")]
+    pub stickied_at: Option<i64>,
+    #[sqlx(try_from = "NullableInt")]
+    pub locked_at: Option<i64>,
+    pub mod_notice: String,
+    #[sqlx(try_from = "NullableInt")]
+    pub held_at: Option<i64>,
+    #[sqlx(try_from = "NullableInt")]
+    pub shadowed_at: Option<i64>,
+    #[sqlx(skip)]
+    pub attachments: Vec<AttachmentRow>,
+    #[sqlx(skip)]
+    pub staff_notes: Vec<StaffNoteRow>,
 }
 
 /// Deserialized DB row containing a submessage (post)
@@ -23,57 +109,447 @@ pub struct SubmessageRow {
     pub board: String,
     pub time: i64,
     pub author: String,
+    pub tripcode: String,
     pub submsg: String,
-    pub image: String,
+    #[sqlx(try_from = "NullableInt")]
+    pub deleted_at: Option<i64>,
+    pub poster_hash: String,
+    pub mod_notice: String,
+    #[sqlx(try_from = "NullableInt")]
+    pub held_at: Option<i64>,
+    #[sqlx(try_from = "NullableInt")]
+    pub shadowed_at: Option<i64>,
+    #[sqlx(skip)]
+    pub attachments: Vec<AttachmentRow>,
+    #[sqlx(skip)]
+    pub staff_notes: Vec<StaffNoteRow>,
+}
+
+impl MessageRow {
+    /// Checks whether the message is shown to a visitor with the given poster hash.
+    /// Held messages are hidden from everyone, shadowed ones are only shown to their poster
+    pub fn visible_to(&self, viewer: &str) -> bool {
+        self.held_at.is_none() && (self.shadowed_at.is_none() || self.poster_hash == viewer)
+    }
+}
+
+impl SubmessageRow {
+    /// Checks whether the submessage is shown to a visitor with the given poster hash.
+    /// Held submessages are hidden from everyone, shadowed ones are only shown to their poster
+    pub fn visible_to(&self, viewer: &str) -> bool {
+        self.held_at.is_none() && (self.shadowed_at.is_none() || self.poster_hash == viewer)
+    }
+}
+
+/// Deserialized DB row containing a media file attached to a message or submessage.
+/// Attachments of messages (threads) have `submsg_id` equal to 0.
+#[allow(dead_code)]
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct AttachmentRow {
+    pub attachment_id: i64,
+    pub msgid: i64,
+    pub submsg_id: i64,
+    pub position: i64,
+    pub stored_name: String,
+    pub original_name: String,
+    pub mime_type: String,
+    pub byte_size: i64,
+    #[sqlx(try_from = "NullableInt")]
+    pub width: Option<i64>,
+    #[sqlx(try_from = "NullableInt")]
+    pub height: Option<i64>,
+    pub content_hash: String,
+}
+
+/// Media file that was saved to `USER_IMAGES_DIR`, but not yet tied to a post
+#[derive(Debug, Clone)]
+pub struct NewAttachment {
+    pub stored_name: String,
+    pub original_name: String,
+    pub mime_type: String,
+    pub byte_size: i64,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub content_hash: String,
+}
+
+impl NewAttachment {
+    /// Collects metadata of a stored media file. Returns `None` if the file can't be read
+    pub fn from_file(path: &Path, original_name: &str) -> Option<NewAttachment> {
+        let stored_name = path.file_name()?.to_string_lossy().to_string();
+        let byte_size = std::fs::metadata(path).ok()?.len();
+
+        // libmagic MIME type detection
+        let cookie = magic::Cookie::open(magic::cookie::Flags::MIME_TYPE).ok()?;
+        let database = Default::default();
+        let cookie = cookie.load(&database).ok()?;
+        let mime_type = cookie.file(path).ok()?;
+
+        // dimensions are only known for images
+        let (width, height) = match imagesize::size(path) {
+            Ok(s) => (Some(s.width as i64), Some(s.height as i64)),
+            Err(_) => (None, None),
+        };
+
+        Some(NewAttachment {
+            stored_name,
+            original_name: original_name.to_string(),
+            mime_type,
+            byte_size: byte_size as i64,
+            width,
+            height,
+            content_hash: sha256::try_digest(path).ok()?,
+        })
+    }
 }
 
-/// Deserialized data about flagged messages/submessages
+/// Marks placed around matched words in search snippets. Snippets are cut from raw post text,
+/// so they are escaped before the marks are turned into HTML
+pub const SNIPPET_MATCH_START: char = '\u{E000}';
+pub const SNIPPET_MATCH_END: char = '\u{E001}';
+
+/// Single full-text search hit, either a thread or a reply
 #[derive(Debug, sqlx::FromRow)]
-pub struct FlaggedRow {
-    pub entry_id: i64,
-    pub msg_type: String,
+pub struct SearchResultRow {
     pub msgid: i64,
-    pub submsg_index: Option<i64>,
+    pub submsg_id: i64,
+    pub board: String,
+    pub time: i64,
+    pub author: String,
+    pub snippet: String,
 }
 
-/// Removes all specified file paths
-pub fn purge_images(paths: Vec<&str>) {
-    if paths != [""] {
-        for i in paths {
-            if i.is_empty() {
-                continue;
-            }
-            match std::fs::remove_file(std::path::Path::new(&format!(
-                "{}/{}",
-                env!("CARGO_MANIFEST_DIR"),
-                i
-            ))) {
-                Ok(_) => log::debug!("Deleted media file: {}", i),
-                Err(_) => log::error!("Media file deletion failed: {}", i),
-            };
+/// Who is shown a newly stored post
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostVisibility {
+    /// Shown to everyone
+    Public,
+    /// Hidden until approved in the dashboard
+    Held,
+    /// Only shown to its poster (shadow ban)
+    Shadowed,
+}
+
+/// Contents of a message or submessage that is about to be stored
+pub struct NewPost<'a> {
+    pub board: &'a str,
+    pub time: i64,
+    pub author: &'a str,
+    /// Tripcode with its `!` or `!!` prefix, or empty
+    pub tripcode: &'a str,
+    pub body: &'a str,
+    pub poster_hash: &'a str,
+    pub attachments: &'a [NewAttachment],
+    pub visibility: PostVisibility,
+}
+
+impl NewPost<'_> {
+    /// Values of `held_at` and `shadowed_at` flags, bound as integers,
+    /// since `Any` driver binds `None` as INT4 in Postgres
+    fn hidden_flags(&self) -> (i64, i64) {
+        (
+            (self.visibility == PostVisibility::Held) as i64,
+            (self.visibility == PostVisibility::Shadowed) as i64,
+        )
+    }
+}
+
+/// Posts of a single poster selected for bulk deletion. Empty `board` and `msgid` equal to 0
+/// match every board and thread; only posts made at or after `since` are selected
+pub struct PosterSelection {
+    pub poster_hash: String,
+    pub board: String,
+    pub msgid: i64,
+    pub since: i64,
+}
+
+/// Thread read from a board export, along with its replies and their numbers
+pub struct ImportedThread<'a> {
+    pub old_msgid: i64,
+    pub post: NewPost<'a>,
+    pub latest_submsg: i64,
+    pub last_submsg_id: i64,
+    pub archived_at: Option<i64>,
+    pub replies: Vec<(i64, NewPost<'a>)>,
+}
+
+/// Directory where media files sent by users are stored
+pub const USER_IMAGES_DIR: &str = "data/user_images";
+
+/// Removes media files of the specified attachments
+pub fn purge_attachments(attachments: &[AttachmentRow]) {
+    for i in attachments {
+        let path = format!("{}/{}", USER_IMAGES_DIR, i.stored_name);
+        match std::fs::remove_file(Path::new(&path)) {
+            Ok(_) => log::debug!("Deleted media file: {}", path),
+            Err(_) => log::error!("Media file deletion failed: {}", path),
+        };
+    }
+}
+
+/// Removes media files that were saved, but never tied to a post
+pub fn discard_new_attachments(attachments: &[NewAttachment]) {
+    for i in attachments {
+        let path = format!("{}/{}", USER_IMAGES_DIR, i.stored_name);
+        if std::fs::remove_file(Path::new(&path)).is_err() {
+            log::error!("Media file deletion failed: {}", path);
+        }
+    }
+}
+
+/// Inserts attachment rows for a single post, keeping the order in which files were sent
+async fn insert_attachments(
+    conn: &mut AnyConnection,
+    msgid: i64,
+    submsg_id: i64,
+    attachments: &[NewAttachment],
+) -> Result<(), sqlx::Error> {
+    for (position, a) in attachments.iter().enumerate() {
+        sqlx::query("INSERT INTO attachments(msgid, submsg_id, position, stored_name, original_name, mime_type, byte_size, width, height, content_hash) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)")
+            .bind(msgid)
+            .bind(submsg_id)
+            .bind(position as i64)
+            .bind(a.stored_name.clone())
+            .bind(a.original_name.clone())
+            .bind(a.mime_type.clone())
+            .bind(a.byte_size)
+            .bind(a.width)
+            .bind(a.height)
+            .bind(a.content_hash.clone())
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Inserts a message along with its attachments, locked if `locked` is set. Returns its number
+async fn insert_message(
+    conn: &mut AnyConnection,
+    post: &NewPost<'_>,
+    locked: bool,
+) -> Result<i64, sqlx::Error> {
+    let (held, shadowed) = post.hidden_flags();
+    let msgid: i64 = sqlx::query("INSERT INTO messages(board, time, author, msg, latest_submsg, poster_hash, held_at, shadowed_at, tripcode, locked_at) VALUES ($1, $2, $3, $4, $5, $6, CASE WHEN $7 = 1 THEN $2 END, CASE WHEN $8 = 1 THEN $2 END, $9, CASE WHEN $10 = 1 THEN $2 END) RETURNING msgid")
+        .bind(post.board.to_string())
+        .bind(post.time)
+        .bind(post.author.to_string())
+        .bind(post.body.to_string())
+        .bind(post.time)
+        .bind(post.poster_hash.to_string())
+        .bind(held)
+        .bind(shadowed)
+        .bind(post.tripcode.to_string())
+        .bind(locked as i64)
+        .fetch_one(&mut *conn)
+        .await?
+        .try_get(0)?;
+    insert_attachments(conn, msgid, 0, post.attachments).await?;
+    Ok(msgid)
+}
+
+/// Deletes messages (along with their submessages and attachments) inside of a transaction.
+/// Returns attachments of deleted messages, so that files can be purged after commit.
+async fn delete_messages_in(
+    conn: &mut AnyConnection,
+    msgids: &[i64],
+) -> Result<Vec<AttachmentRow>, sqlx::Error> {
+    let mut deleted_attachments = Vec::new();
+    for msgid in msgids {
+        deleted_attachments.extend(
+            sqlx::query_as::<_, AttachmentRow>("SELECT * FROM attachments WHERE msgid=$1")
+                .bind(msgid)
+                .fetch_all(&mut *conn)
+                .await?,
+        );
+        // the submessages and attachment rows are deleted by cascade
+        sqlx::query("DELETE FROM messages WHERE msgid=$1")
+            .bind(msgid)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(deleted_attachments)
+}
+
+/// Archives or deletes least active threads that don't fit into `hard_limit` of a board.
+/// Sticky threads don't count towards it. Shadowed threads are only seen by their posters,
+/// and held threads by nobody yet, so both are limited separately from visible ones.
+/// Held threads are always deleted, as they were never approved and would stay in the approval queue
+/// if archived. Returns attachments of deleted threads, so that files can be purged after commit.
+async fn enforce_board_limit(
+    conn: &mut AnyConnection,
+    board: &str,
+    hard_limit: i64,
+    archive: bool,
+    time: i64,
+) -> Result<Vec<AttachmentRow>, sqlx::Error> {
+    let mut deleted = least_active_threads(conn, board, hard_limit, "held_at IS NOT NULL").await?;
+    let mut expired = least_active_threads(
+        conn,
+        board,
+        hard_limit,
+        "held_at IS NULL AND shadowed_at IS NOT NULL",
+    )
+    .await?;
+    expired.extend(
+        least_active_threads(
+            conn,
+            board,
+            hard_limit,
+            "held_at IS NULL AND shadowed_at IS NULL",
+        )
+        .await?,
+    );
+    if archive {
+        for expired_msgid in expired {
+            sqlx::query("UPDATE messages SET archived_at=$1 WHERE msgid=$2")
+                .bind(time)
+                .bind(expired_msgid)
+                .execute(&mut *conn)
+                .await?;
+            log::debug!("Archiving least active message: {}", expired_msgid);
         }
+    } else {
+        deleted.extend(expired);
     }
+    log::debug!("Deleting least active messages: {:?}", deleted);
+    delete_messages_in(conn, &deleted).await
+}
+
+/// Returns live non-sticky threads of a board matching `condition`
+/// that don't fit into `hard_limit` of them
+async fn least_active_threads(
+    conn: &mut AnyConnection,
+    board: &str,
+    hard_limit: i64,
+    condition: &str,
+) -> Result<Vec<i64>, sqlx::Error> {
+    collect_ids(sqlx::query(&format!("SELECT msgid FROM messages WHERE board=$1 AND archived_at IS NULL AND deleted_at IS NULL AND stickied_at IS NULL AND {0} AND msgid NOT IN (SELECT msgid FROM messages WHERE board=$1 AND archived_at IS NULL AND deleted_at IS NULL AND stickied_at IS NULL AND {0} ORDER BY latest_submsg DESC, msgid DESC LIMIT $2)", condition))
+        .bind(board.to_string())
+        .bind(hard_limit)
+        .fetch_all(&mut *conn)
+        .await?)
+}
+
+/// Collects first column of returned rows as IDs
+fn collect_ids(rows: Vec<sqlx::any::AnyRow>) -> Result<Vec<i64>, sqlx::Error> {
+    rows.iter().map(|row| row.try_get(0)).collect()
+}
+
+/// Condition excluding search hits from deleted and hidden posts (and replies to such threads).
+/// Shadowed posts of the viewer, whose poster hash is bound as `$viewer_param`, are kept
+fn visible_search_hits(viewer_param: usize) -> String {
+    format!("NOT EXISTS (SELECT 1 FROM messages WHERE messages.msgid = post_search.msgid AND (messages.deleted_at IS NOT NULL OR messages.held_at IS NOT NULL OR (messages.shadowed_at IS NOT NULL AND messages.poster_hash <> ${0}))) AND NOT EXISTS (SELECT 1 FROM submessages WHERE submessages.parent_msg = post_search.msgid AND submessages.submsg_id = post_search.submsg_id AND (submessages.deleted_at IS NOT NULL OR submessages.held_at IS NOT NULL OR (submessages.shadowed_at IS NOT NULL AND submessages.poster_hash <> ${0})))", viewer_param)
 }
 
 /// Wrapper for the DB client
 pub struct DatabaseWrapper {
     db_pool: AnyPool,
+    kind: DatabaseKind,
 }
 
 impl DatabaseWrapper {
-    pub async fn new() -> Result<DatabaseWrapper, sqlx::Error> {
-        // loading database drivers
-        sqlx::any::install_default_drivers();
-
+    pub async fn new(config: &BoardConfig) -> Result<DatabaseWrapper, sqlx::Error> {
         let url = match std::env::var("DATABASE_URL") {
             Ok(u) => u,
             Err(_) => panic!("DATABASE_URL variable is not set (check .env file)"),
         };
+        DatabaseWrapper::connect(&url, config).await
+    }
+
+    /// Connects to the database at `url` using pool settings from config
+    pub async fn connect(url: &str, config: &BoardConfig) -> Result<DatabaseWrapper, sqlx::Error> {
+        // loading database drivers
+        sqlx::any::install_default_drivers();
+
+        let kind = match DatabaseKind::from_url(url) {
+            Some(k) => k,
+            None => panic!("Database URL must point to either SQLite or Postgres database"),
+        };
+
+        // SQLite connections need some tuning to handle concurrent writers
+        let mut sqlite_pragmas = format!("PRAGMA busy_timeout = {};", config.sqlite_busy_timeout);
+        if config.sqlite_wal {
+            sqlite_pragmas.push_str(" PRAGMA journal_mode = WAL;");
+        }
 
         // connecting to the database
-        let pool = AnyPoolOptions::new().connect(&url).await?;
+        let pool = AnyPoolOptions::new()
+            .max_connections(config.db_max_connections)
+            .acquire_timeout(Duration::from_secs(config.db_acquire_timeout))
+            .after_connect(move |conn, _meta| {
+                let pragmas = sqlite_pragmas.clone();
+                Box::pin(async move {
+                    if kind == DatabaseKind::Sqlite {
+                        conn.execute(pragmas.as_str()).await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect(url)
+            .await?;
+
+        Ok(DatabaseWrapper {
+            db_pool: pool,
+            kind,
+        })
+    }
 
-        Ok(DatabaseWrapper { db_pool: pool })
+    /// Returns latest applied migration version (0 if database is empty)
+    pub async fn schema_version(&self) -> Result<i64, sqlx::Error> {
+        self.db_pool.execute(migrations::VERSION_TABLE).await?;
+        let row = sqlx::query("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+            .fetch_one(&self.db_pool)
+            .await?;
+        row.try_get(0)
+    }
+
+    /// Returns migrations that were not yet applied to the database
+    pub async fn pending_migrations(
+        &self,
+    ) -> Result<Vec<&'static migrations::Migration>, sqlx::Error> {
+        let current = self.schema_version().await?;
+        Ok(migrations::MIGRATIONS
+            .iter()
+            .filter(|m| m.version > current)
+            .collect())
+    }
+
+    /// Applies all pending migrations, each one in a separate transaction.
+    /// Returns the resulting schema version.
+    pub async fn apply_migrations(&self) -> Result<i64, sqlx::Error> {
+        let pending = self.pending_migrations().await?;
+        if pending.is_empty() {
+            log::info!(
+                "Database schema is up to date (version {})",
+                migrations::latest_version()
+            );
+        }
+        for migration in pending {
+            let script = match self.kind {
+                DatabaseKind::Sqlite => migration.sqlite,
+                DatabaseKind::Postgres => migration.postgres,
+            };
+            let mut transaction = self.db_pool.begin().await?;
+            transaction.execute(script).await?;
+            if let Some(step) = migration.data {
+                migrations::run_data_migration(step, &mut transaction).await?;
+            }
+            sqlx::query(
+                "INSERT INTO schema_version(version, description, applied_at) VALUES ($1, $2, $3)",
+            )
+            .bind(migration.version)
+            .bind(migration.description)
+            .bind(crate::html_proc::since_epoch())
+            .execute(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+            log::info!(
+                "Applied database migration {} ({})",
+                migration.version,
+                migration.description
+            );
+        }
+        self.schema_version().await
     }
 
     fn log_query_status<T: core::fmt::Debug>(status: Result<T, sqlx::Error>, operation: &str) {
@@ -84,91 +560,248 @@ impl DatabaseWrapper {
     }
 
     pub async fn count_messages(&self, board: &str) -> Result<i64, sqlx::Error> {
-        let count_struct = sqlx::query("SELECT COUNT(msgid) FROM messages WHERE board=$1")
-            .bind(String::from(board))
-            .fetch_one(&self.db_pool)
-            .await;
+        let count_struct =
+            sqlx::query("SELECT COUNT(msgid) FROM messages WHERE board=$1 AND deleted_at IS NULL")
+                .bind(String::from(board))
+                .fetch_one(&self.db_pool)
+                .await;
         Ok(count_struct.unwrap().try_get(0).unwrap())
     }
 
     pub async fn count_board_submessages(&self, board: &str) -> Result<i64, sqlx::Error> {
-        let count_struct = sqlx::query("SELECT COUNT(submsg_id) FROM submessages WHERE board=$1")
-            .bind(String::from(board))
-            .fetch_one(&self.db_pool)
-            .await;
+        let count_struct = sqlx::query(
+            "SELECT COUNT(submsg_id) FROM submessages WHERE board=$1 AND deleted_at IS NULL",
+        )
+        .bind(String::from(board))
+        .fetch_one(&self.db_pool)
+        .await;
         Ok(count_struct.unwrap().try_get(0).unwrap())
     }
 
-    pub async fn count_submessages(&self, msgid: i64) -> Result<i64, sqlx::Error> {
-        let count_struct = sqlx::query("SELECT COUNT(*) FROM submessages WHERE parent_msg=$1")
-            .bind(msgid)
-            .fetch_one(&self.db_pool)
-            .await;
-        count_struct?.try_get(0)
+    /// Gets attachments of a single message (`submsg_id` = 0) or submessage
+    pub async fn get_attachments(
+        &self,
+        msgid: i64,
+        submsg_id: i64,
+    ) -> Result<Vec<AttachmentRow>, sqlx::Error> {
+        sqlx::query_as::<_, AttachmentRow>(
+            "SELECT * FROM attachments WHERE msgid=$1 AND submsg_id=$2 ORDER BY position",
+        )
+        .bind(msgid)
+        .bind(submsg_id)
+        .fetch_all(&self.db_pool)
+        .await
     }
 
-    pub async fn delete_least_active(&self, board: &str) {
-        let selected: MessageRow = sqlx::query_as::<_, MessageRow>("SELECT * FROM messages WHERE latest_submsg = (SELECT MIN(latest_submsg) FROM messages WHERE board=$1)")
-            .bind(String::from(board))
-            .fetch_one(&self.db_pool).await.unwrap();
-        purge_images(selected.image.split(';').collect());
-        DatabaseWrapper::log_query_status(
-            sqlx::query("DELETE FROM messages WHERE msgid = $1")
-                .bind(selected.msgid.to_string())
-                .execute(&self.db_pool)
-                .await,
-            "Deleting least active message",
-        );
+    /// Gets attachments of a message and all of its submessages
+    pub async fn get_thread_attachments(
+        &self,
+        msgid: i64,
+    ) -> Result<Vec<AttachmentRow>, sqlx::Error> {
+        sqlx::query_as::<_, AttachmentRow>(
+            "SELECT * FROM attachments WHERE msgid=$1 ORDER BY submsg_id, position",
+        )
+        .bind(msgid)
+        .fetch_all(&self.db_pool)
+        .await
     }
 
-    pub async fn get_submessages(&self, msgid: i64) -> Result<Vec<SubmessageRow>, sqlx::Error> {
-        sqlx::query_as::<_, SubmessageRow>("SELECT * FROM submessages WHERE parent_msg=$1")
-            .bind(msgid)
-            .fetch_all(&self.db_pool)
-            .await
+    /// Fills `attachments` field of message rows
+    async fn with_message_attachments(
+        &self,
+        mut rows: Vec<MessageRow>,
+    ) -> Result<Vec<MessageRow>, sqlx::Error> {
+        for row in rows.iter_mut() {
+            row.attachments = self.get_attachments(row.msgid, 0).await?;
+        }
+        Ok(rows)
+    }
+
+    /// Fills `attachments` field of submessage rows
+    async fn with_submessage_attachments(
+        &self,
+        mut rows: Vec<SubmessageRow>,
+    ) -> Result<Vec<SubmessageRow>, sqlx::Error> {
+        for row in rows.iter_mut() {
+            row.attachments = self.get_attachments(row.parent_msg, row.submsg_id).await?;
+        }
+        Ok(rows)
     }
 
+    /// Gets submessages of a thread shown to a visitor with `viewer` poster hash
+    /// (empty to skip every hidden submessage)
+    pub async fn get_submessages(
+        &self,
+        msgid: i64,
+        viewer: &str,
+    ) -> Result<Vec<SubmessageRow>, sqlx::Error> {
+        let mut rows = sqlx::query_as::<_, SubmessageRow>(
+            "SELECT * FROM submessages WHERE parent_msg=$1 AND deleted_at IS NULL AND held_at IS NULL AND (shadowed_at IS NULL OR poster_hash = $2) ORDER BY submsg_id",
+        )
+        .bind(msgid)
+        .bind(viewer.to_string())
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        // fetching media of the whole thread at once
+        let mut attachments = self.get_thread_attachments(msgid).await?;
+        for row in rows.iter_mut() {
+            let (own, rest) = attachments
+                .into_iter()
+                .partition(|a| a.submsg_id == row.submsg_id);
+            row.attachments = own;
+            attachments = rest;
+        }
+        Ok(rows)
+    }
+
+    /// Counts threads and replies posted on a board in the last `time_period` seconds
     pub async fn get_posting_rate(
         &self,
         board: &str,
         time_period: i64,
     ) -> Result<i64, sqlx::Error> {
-        let count_struct = sqlx::query("SELECT COUNT(*) FROM (SELECT board, time FROM messages UNION SELECT board, time FROM submessages) WHERE board=$1 AND time > $2")
-            .bind(board)
-            // now we select all messages sent later than "current time" - `time_period` seconds ago
+        // now we select all messages sent later than "current time" - `time_period` seconds ago
+        let count_struct = sqlx::query("SELECT (SELECT COUNT(*) FROM messages WHERE board=$1 AND time > $2) + (SELECT COUNT(*) FROM submessages WHERE board=$1 AND time > $2)")
+            .bind(board.to_string())
             .bind(crate::html_proc::since_epoch() - time_period)
             .fetch_one(&self.db_pool)
-            .await;
-        Ok(count_struct.unwrap().try_get(0).unwrap())
+            .await?;
+        count_struct.try_get(0)
     }
 
+    /// Gets a page of active messages (threads) shown to a visitor with `viewer` poster hash
     pub async fn get_messages(
         &self,
         board: &str,
         page: i64,
         limit: i64,
+        viewer: &str,
     ) -> Result<Vec<MessageRow>, sqlx::Error> {
-        sqlx::query_as::<_, MessageRow>(
-            "SELECT * FROM messages WHERE board=$1 ORDER BY latest_submsg DESC LIMIT $3 OFFSET $2",
+        let rows = sqlx::query_as::<_, MessageRow>(
+            "SELECT * FROM messages WHERE board=$1 AND archived_at IS NULL AND deleted_at IS NULL AND held_at IS NULL AND (shadowed_at IS NULL OR poster_hash = $4) ORDER BY stickied_at IS NULL, latest_submsg DESC LIMIT $3 OFFSET $2",
         )
         .bind(board.to_string())
         .bind((page - 1) * limit)
         .bind(limit)
+        .bind(viewer.to_string())
         .fetch_all(&self.db_pool)
-        .await
+        .await?;
+        self.with_message_attachments(rows).await
     }
 
-    pub async fn search_messages(
+    /// Gets a page of archived messages (threads) shown to a visitor with `viewer` poster hash
+    pub async fn get_archived_messages(
         &self,
         board: &str,
         page: i64,
         limit: i64,
-        substring: &str,
+        viewer: &str,
     ) -> Result<Vec<MessageRow>, sqlx::Error> {
-        sqlx::query_as::<_, MessageRow>(
-                "SELECT * FROM (SELECT * FROM messages WHERE board=$1 ORDER BY latest_submsg DESC LIMIT $4 OFFSET $3) AS limited WHERE limited.msg LIKE '%' || $2 || '%'")
-                .bind(board.to_string()).bind(substring.to_string()).bind(page).bind(limit)
-            .fetch_all(&self.db_pool).await
+        let rows = sqlx::query_as::<_, MessageRow>(
+            "SELECT * FROM messages WHERE board=$1 AND archived_at IS NOT NULL AND deleted_at IS NULL AND held_at IS NULL AND (shadowed_at IS NULL OR poster_hash = $4) ORDER BY archived_at DESC, msgid DESC LIMIT $3 OFFSET $2",
+        )
+        .bind(board.to_string())
+        .bind((page - 1) * limit)
+        .bind(limit)
+        .bind(viewer.to_string())
+        .fetch_all(&self.db_pool)
+        .await?;
+        self.with_message_attachments(rows).await
+    }
+
+    /// Gets every public message (thread) on a board, including archived ones, oldest first
+    pub async fn get_all_messages(&self, board: &str) -> Result<Vec<MessageRow>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, MessageRow>(
+            "SELECT * FROM messages WHERE board=$1 AND deleted_at IS NULL AND held_at IS NULL AND shadowed_at IS NULL ORDER BY msgid",
+        )
+        .bind(board.to_string())
+        .fetch_all(&self.db_pool)
+        .await?;
+        self.with_message_attachments(rows).await
+    }
+
+    /// Turns user input into FTS5 query, quoting every term to avoid syntax errors
+    fn fts5_query(raw: &str) -> String {
+        raw.split_whitespace()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Ranked full-text search over threads and replies on a board.
+    /// Threads are returned with `submsg_id` equal to 0; hidden posts are only found
+    /// if they are shown to `viewer`.
+    pub async fn search_posts(
+        &self,
+        board: &str,
+        search_string: &str,
+        page: i64,
+        limit: i64,
+        viewer: &str,
+    ) -> Result<Vec<SearchResultRow>, sqlx::Error> {
+        match self.kind {
+            DatabaseKind::Sqlite => {
+                let fts_query = DatabaseWrapper::fts5_query(search_string);
+                if fts_query.is_empty() {
+                    return Ok(Vec::new());
+                }
+                sqlx::query_as::<_, SearchResultRow>(
+                    &format!("SELECT CAST(msgid AS BIGINT) AS msgid, CAST(submsg_id AS BIGINT) AS submsg_id, board, CAST(time AS BIGINT) AS time, author, snippet(post_search, 0, '{}', '{}', '...', 24) AS snippet FROM post_search WHERE post_search MATCH $1 AND board=$2 AND {} ORDER BY rank LIMIT $4 OFFSET $3", SNIPPET_MATCH_START, SNIPPET_MATCH_END, visible_search_hits(5)),
+                )
+                .bind(fts_query)
+                .bind(board.to_string())
+                .bind((page - 1) * limit)
+                .bind(limit)
+                .bind(viewer.to_string())
+                .fetch_all(&self.db_pool)
+                .await
+            }
+            DatabaseKind::Postgres => {
+                sqlx::query_as::<_, SearchResultRow>(
+                    &format!("SELECT msgid, submsg_id, board, time, author, ts_headline('simple', body, query, 'StartSel={}, StopSel={}, MaxWords=24, MinWords=8') AS snippet FROM post_search, plainto_tsquery('simple', $1) AS query WHERE body_vector @@ query AND board=$2 AND {} ORDER BY ts_rank(body_vector, query) DESC, time DESC LIMIT $4 OFFSET $3", SNIPPET_MATCH_START, SNIPPET_MATCH_END, visible_search_hits(5)),
+                )
+                .bind(search_string.to_string())
+                .bind(board.to_string())
+                .bind((page - 1) * limit)
+                .bind(limit)
+                .bind(viewer.to_string())
+                .fetch_all(&self.db_pool)
+                .await
+            }
+        }
+    }
+
+    /// Counts all full-text search hits on a board
+    pub async fn count_search_results(
+        &self,
+        board: &str,
+        search_string: &str,
+        viewer: &str,
+    ) -> Result<i64, sqlx::Error> {
+        let count_struct = match self.kind {
+            DatabaseKind::Sqlite => {
+                let fts_query = DatabaseWrapper::fts5_query(search_string);
+                if fts_query.is_empty() {
+                    return Ok(0);
+                }
+                sqlx::query(&format!("SELECT COUNT(*) FROM post_search WHERE post_search MATCH $1 AND board=$2 AND {}", visible_search_hits(3)))
+                    .bind(fts_query)
+                    .bind(board.to_string())
+                    .bind(viewer.to_string())
+                    .fetch_one(&self.db_pool)
+                    .await?
+            }
+            DatabaseKind::Postgres => {
+                sqlx::query(&format!("SELECT COUNT(*) FROM post_search WHERE body_vector @@ plainto_tsquery('simple', $1) AND board=$2 AND {}", visible_search_hits(3)))
+                    .bind(search_string.to_string())
+                    .bind(board.to_string())
+                    .bind(viewer.to_string())
+                    .fetch_one(&self.db_pool)
+                    .await?
+            }
+        };
+        count_struct.try_get(0)
     }
 
     pub async fn get_last_message(&self, board: &str) -> Result<MessageRow, sqlx::Error> {
@@ -192,11 +825,24 @@ impl DatabaseWrapper {
         .await
     }
 
-    pub async fn get_single_message(&self, msgid: i64) -> Result<MessageRow, sqlx::Error> {
-        sqlx::query_as::<_, MessageRow>("SELECT * FROM messages WHERE msgid=$1")
+    /// Returns board of a thread, whether it was deleted or not
+    pub async fn get_thread_board(&self, msgid: i64) -> Result<String, sqlx::Error> {
+        sqlx::query("SELECT board FROM messages WHERE msgid=$1")
             .bind(msgid)
             .fetch_one(&self.db_pool)
-            .await
+            .await?
+            .try_get(0)
+    }
+
+    pub async fn get_single_message(&self, msgid: i64) -> Result<MessageRow, sqlx::Error> {
+        let mut row = sqlx::query_as::<_, MessageRow>(
+            "SELECT * FROM messages WHERE msgid=$1 AND deleted_at IS NULL",
+        )
+        .bind(msgid)
+        .fetch_one(&self.db_pool)
+        .await?;
+        row.attachments = self.get_attachments(msgid, 0).await?;
+        Ok(row)
     }
 
     pub async fn get_single_submessage(
@@ -204,49 +850,22 @@ impl DatabaseWrapper {
         parent_id: i64,
         submsgid: i64,
     ) -> Result<SubmessageRow, sqlx::Error> {
-        sqlx::query_as::<_, SubmessageRow>(
-            "SELECT * FROM submessages WHERE parent_msg=$1 AND submsg_id=$2",
+        let mut row = sqlx::query_as::<_, SubmessageRow>(
+            "SELECT * FROM submessages WHERE parent_msg=$1 AND submsg_id=$2 AND deleted_at IS NULL AND parent_msg IN (SELECT msgid FROM messages WHERE deleted_at IS NULL)",
         )
         .bind(parent_id)
         .bind(submsgid)
         .fetch_one(&self.db_pool)
-        .await
-    }
-
-    pub async fn get_flagged_messages(&self) -> Result<Vec<MessageRow>, sqlx::Error> {
-        sqlx::query_as::<_, MessageRow>("SELECT * FROM messages WHERE msgid IN (SELECT msgid FROM flagged_messages WHERE msg_type='msg') ORDER BY time DESC")
-            .fetch_all(&self.db_pool)
-            .await
-    }
-
-    pub async fn get_flagged_submessages(&self) -> Result<Vec<SubmessageRow>, sqlx::Error> {
-        sqlx::query_as::<_, SubmessageRow>("SELECT * FROM submessages WHERE parent_msg IN (SELECT msgid FROM flagged_messages WHERE msg_type='submsg') AND submsg_id IN (SELECT submsg_index FROM flagged_messages) ORDER BY time DESC")
-            .fetch_all(&self.db_pool)
-            .await
-    }
-
-    pub async fn update_message_activity(&self, msgid: i64, since_epoch: i64) {
-        DatabaseWrapper::log_query_status(
-            sqlx::query("UPDATE messages SET latest_submsg = $1 WHERE msgid = $2")
-                .bind(msgid.to_string())
-                .bind(since_epoch.to_string())
-                .execute(&self.db_pool)
-                .await,
-            "Updating message activity timer",
-        );
+        .await?;
+        row.attachments = self.get_attachments(parent_id, submsgid).await?;
+        Ok(row)
     }
 
+    /// Moves a message (thread) into trash. It stays there until restored or purged
     pub async fn delete_msg(&self, msgid: i64) {
-        let selected = self.get_single_message(msgid).await.unwrap();
-        purge_images(selected.image.split(';').collect());
-        // getting all submessages so that we can delete their assigned files
-        let submsgs = self.get_submessages(msgid).await.unwrap();
-        for i in submsgs {
-            purge_images(i.image.split(';').collect());
-        }
-        // the submessages themselves are deleted by cascade
         DatabaseWrapper::log_query_status(
-            sqlx::query("DELETE FROM messages WHERE msgid=$1")
+            sqlx::query("UPDATE messages SET deleted_at=$1 WHERE msgid=$2 AND deleted_at IS NULL")
+                .bind(crate::html_proc::since_epoch())
                 .bind(msgid)
                 .execute(&self.db_pool)
                 .await,
@@ -254,11 +873,11 @@ impl DatabaseWrapper {
         );
     }
 
+    /// Moves a submessage into trash. It stays there until restored or purged
     pub async fn delete_submsg(&self, msgid: i64, submsgid: i64) {
-        let selected = self.get_single_submessage(msgid, submsgid).await.unwrap();
-        purge_images(selected.image.split(';').collect());
         DatabaseWrapper::log_query_status(
-            sqlx::query("DELETE FROM submessages WHERE parent_msg=$1 AND submsg_id=$2")
+            sqlx::query("UPDATE submessages SET deleted_at=$1 WHERE parent_msg=$2 AND submsg_id=$3 AND deleted_at IS NULL")
+                .bind(crate::html_proc::since_epoch())
                 .bind(msgid)
                 .bind(submsgid)
                 .execute(&self.db_pool)
@@ -267,48 +886,479 @@ impl DatabaseWrapper {
         );
     }
 
-    pub async fn insert_to_messages(
+    /// Counts live threads and replies matching a poster selection
+    pub async fn count_poster_posts(
         &self,
-        board: &str,
-        time: i64,
-        author: &str,
-        msg: &str,
-        image: &str,
-        latest_submsg: i64,
-    ) {
-        DatabaseWrapper::log_query_status(
-            sqlx::query("INSERT INTO messages(board, time, author, msg, image, latest_submsg) VALUES ($1, $2, $3, $4, $5, $6)")
-            .bind(board.to_string()).bind(time).bind(author.to_string()).bind(msg.to_string()).bind(image.to_string()).bind(latest_submsg).execute(&self.db_pool).await, "Inserting row into messages table"
-        );
+        selection: &PosterSelection,
+    ) -> Result<(i64, i64), sqlx::Error> {
+        let threads: i64 = sqlx::query("SELECT COUNT(*) FROM messages WHERE poster_hash=$1 AND time >= $2 AND ($3 = '' OR board = $3) AND ($4 = 0 OR msgid = $4) AND deleted_at IS NULL")
+            .bind(selection.poster_hash.to_string())
+            .bind(selection.since)
+            .bind(selection.board.to_string())
+            .bind(selection.msgid)
+            .fetch_one(&self.db_pool)
+            .await?
+            .try_get(0)?;
+        let replies: i64 = sqlx::query("SELECT COUNT(*) FROM submessages WHERE poster_hash=$1 AND time >= $2 AND ($3 = '' OR board = $3) AND ($4 = 0 OR parent_msg = $4) AND deleted_at IS NULL")
+            .bind(selection.poster_hash.to_string())
+            .bind(selection.since)
+            .bind(selection.board.to_string())
+            .bind(selection.msgid)
+            .fetch_one(&self.db_pool)
+            .await?
+            .try_get(0)?;
+        Ok((threads, replies))
     }
 
-    pub async fn insert_to_submessages(
+    /// Lists live threads and replies matching a poster selection, as pairs of
+    /// message and submessage numbers (0 for threads)
+    pub async fn get_poster_posts(
         &self,
-        parent_msg: i64,
-        submsg_id: i64,
-        board: &str,
-        time: i64,
-        author: &str,
-        submsg: &str,
-        image: &str,
-    ) {
+        selection: &PosterSelection,
+    ) -> Result<Vec<(i64, i64)>, sqlx::Error> {
+        let threads = collect_ids(sqlx::query("SELECT msgid FROM messages WHERE poster_hash=$1 AND time >= $2 AND ($3 = '' OR board = $3) AND ($4 = 0 OR msgid = $4) AND deleted_at IS NULL")
+            .bind(selection.poster_hash.to_string())
+            .bind(selection.since)
+            .bind(selection.board.to_string())
+            .bind(selection.msgid)
+            .fetch_all(&self.db_pool)
+            .await?)?;
+        let mut posts: Vec<(i64, i64)> = threads.into_iter().map(|msgid| (msgid, 0)).collect();
+        let replies = sqlx::query("SELECT parent_msg, submsg_id FROM submessages WHERE poster_hash=$1 AND time >= $2 AND ($3 = '' OR board = $3) AND ($4 = 0 OR parent_msg = $4) AND deleted_at IS NULL")
+            .bind(selection.poster_hash.to_string())
+            .bind(selection.since)
+            .bind(selection.board.to_string())
+            .bind(selection.msgid)
+            .fetch_all(&self.db_pool)
+            .await?;
+        for row in replies {
+            posts.push((row.try_get(0)?, row.try_get(1)?));
+        }
+        Ok(posts)
+    }
+
+    /// Moves posts listed by `get_poster_posts` into trash, skipping ones that already are there.
+    /// Returns numbers of deleted threads and replies
+    pub async fn delete_poster_posts(
+        &self,
+        posts: &[(i64, i64)],
+    ) -> Result<(u64, u64), sqlx::Error> {
+        let now = crate::html_proc::since_epoch();
+        let mut transaction = self.db_pool.begin().await?;
+        let (mut threads, mut replies) = (0, 0);
+        for &(msgid, submsg_id) in posts {
+            if submsg_id == 0 {
+                threads += sqlx::query(
+                    "UPDATE messages SET deleted_at=$1 WHERE msgid=$2 AND deleted_at IS NULL",
+                )
+                .bind(now)
+                .bind(msgid)
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected();
+            } else {
+                replies += sqlx::query("UPDATE submessages SET deleted_at=$1 WHERE parent_msg=$2 AND submsg_id=$3 AND deleted_at IS NULL")
+                    .bind(now)
+                    .bind(msgid)
+                    .bind(submsg_id)
+                    .execute(&mut *transaction)
+                    .await?
+                    .rows_affected();
+            }
+        }
+        transaction.commit().await?;
+        Ok((threads, replies))
+    }
+
+    pub async fn restore_msg(&self, msgid: i64) {
         DatabaseWrapper::log_query_status(
-            sqlx::query("INSERT INTO submessages(parent_msg, submsg_id, board, time, author, submsg, image) VALUES ($1, $2, $3, $4, $5, $6, $7)")
-            .bind(parent_msg).bind(submsg_id).bind(board.to_string()).bind(time).bind(author.to_string()).bind(submsg.to_string()).bind(image.to_string()).execute(&self.db_pool).await, "Inserting row into submessages table"
+            sqlx::query("UPDATE messages SET deleted_at=NULL WHERE msgid=$1")
+                .bind(msgid)
+                .execute(&self.db_pool)
+                .await,
+            "Restoring message",
         );
     }
 
-    pub async fn insert_to_flagged(&self, msg_type: String, msgid: i64, submsgid: Option<i64>) {
+    pub async fn restore_submsg(&self, msgid: i64, submsgid: i64) {
         DatabaseWrapper::log_query_status(
             sqlx::query(
-                "INSERT INTO flagged_messages(msg_type, msgid, submsg_index) VALUES ($1, $2, $3)",
+                "UPDATE submessages SET deleted_at=NULL WHERE parent_msg=$1 AND submsg_id=$2",
             )
-            .bind(msg_type)
             .bind(msgid)
-            .bind(submsgid.unwrap_or(0))
+            .bind(submsgid)
             .execute(&self.db_pool)
             .await,
-            "Inserting row into flagged_messages table",
+            "Restoring submessage",
+        );
+    }
+
+    /// Pins a thread to the top of its board and catalog, or unpins it
+    pub async fn set_sticky(&self, msgid: i64, sticky: bool) {
+        let query = match sticky {
+            true => "UPDATE messages SET stickied_at=$1 WHERE msgid=$2 AND stickied_at IS NULL",
+            false => "UPDATE messages SET stickied_at=NULL WHERE msgid=$1",
+        };
+        let mut query = sqlx::query(query);
+        if sticky {
+            query = query.bind(crate::html_proc::since_epoch());
+        }
+        DatabaseWrapper::log_query_status(
+            query.bind(msgid).execute(&self.db_pool).await,
+            "Changing sticky state of message",
+        );
+    }
+
+    /// Locks a thread against new replies, or unlocks it
+    pub async fn set_locked(&self, msgid: i64, locked: bool) {
+        let query = match locked {
+            true => "UPDATE messages SET locked_at=$1 WHERE msgid=$2 AND locked_at IS NULL",
+            false => "UPDATE messages SET locked_at=NULL WHERE msgid=$1",
+        };
+        let mut query = sqlx::query(query);
+        if locked {
+            query = query.bind(crate::html_proc::since_epoch());
+        }
+        DatabaseWrapper::log_query_status(
+            query.bind(msgid).execute(&self.db_pool).await,
+            "Changing lock state of message",
         );
     }
+
+    /// Moves a thread along with all its replies to another board. Post numbers stay the same,
+    /// so links to them keep resolving. The destination board limit is then enforced
+    /// the same way as for new threads. `notice`, if given, is left on the old board
+    /// as a locked thread. Returns false if there is no such live thread
+    pub async fn move_thread(
+        &self,
+        msgid: i64,
+        board: &str,
+        hard_limit: i64,
+        archive: bool,
+        notice: Option<&NewPost<'_>>,
+    ) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.db_pool.begin().await?;
+        let moved =
+            sqlx::query("UPDATE messages SET board=$1 WHERE msgid=$2 AND deleted_at IS NULL")
+                .bind(board.to_string())
+                .bind(msgid)
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected();
+        if moved == 0 {
+            return Ok(false);
+        }
+        sqlx::query("UPDATE submessages SET board=$1 WHERE parent_msg=$2")
+            .bind(board.to_string())
+            .bind(msgid)
+            .execute(&mut *transaction)
+            .await?;
+        // the notice replaces the moved thread, so the old board limit isn't enforced for it.
+        // It's inserted before threads are evicted, so it can't reuse the number of one
+        if let Some(notice) = notice {
+            insert_message(&mut transaction, notice, true).await?;
+        }
+        let expired_attachments = enforce_board_limit(
+            &mut transaction,
+            board,
+            hard_limit,
+            archive,
+            crate::html_proc::since_epoch(),
+        )
+        .await?;
+        transaction.commit().await?;
+        purge_attachments(&expired_attachments);
+        Ok(true)
+    }
+
+    /// Gets live threads that are either sticky or locked, newest first
+    pub async fn get_moderated_threads(&self) -> Result<Vec<MessageRow>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, MessageRow>("SELECT * FROM messages WHERE (stickied_at IS NOT NULL OR locked_at IS NOT NULL) AND deleted_at IS NULL ORDER BY msgid DESC")
+            .fetch_all(&self.db_pool)
+            .await?;
+        self.with_message_attachments(rows).await
+    }
+
+    /// Gets messages (threads) in trash, most recently deleted first
+    pub async fn get_deleted_messages(&self) -> Result<Vec<MessageRow>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, MessageRow>(
+            "SELECT * FROM messages WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+        self.with_message_attachments(rows).await
+    }
+
+    /// Gets submessages in trash, most recently deleted first.
+    /// Submessages of deleted threads are left out, since they are restored along with the thread.
+    pub async fn get_deleted_submessages(&self) -> Result<Vec<SubmessageRow>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, SubmessageRow>("SELECT * FROM submessages WHERE deleted_at IS NOT NULL AND parent_msg IN (SELECT msgid FROM messages WHERE deleted_at IS NULL) ORDER BY deleted_at DESC")
+            .fetch_all(&self.db_pool)
+            .await?;
+        self.with_submessage_attachments(rows).await
+    }
+
+    /// Permanently deletes posts that were in trash for longer than `retention` seconds,
+    /// along with their media files. Returns number of purged posts.
+    pub async fn purge_trash(&self, retention: i64) -> Result<usize, sqlx::Error> {
+        let cutoff = crate::html_proc::since_epoch() - retention;
+        let mut transaction = self.db_pool.begin().await?;
+
+        let expired_msgs = collect_ids(
+            sqlx::query("SELECT msgid FROM messages WHERE deleted_at < $1")
+                .bind(cutoff)
+                .fetch_all(&mut *transaction)
+                .await?,
+        )?;
+        let mut expired_attachments = delete_messages_in(&mut transaction, &expired_msgs).await?;
+
+        let expired_submsgs =
+            sqlx::query("SELECT parent_msg, submsg_id FROM submessages WHERE deleted_at < $1")
+                .bind(cutoff)
+                .fetch_all(&mut *transaction)
+                .await?;
+        for row in expired_submsgs.iter() {
+            let parent_msg: i64 = row.try_get(0)?;
+            let submsg_id: i64 = row.try_get(1)?;
+            expired_attachments.extend(
+                sqlx::query_as::<_, AttachmentRow>(
+                    "SELECT * FROM attachments WHERE msgid=$1 AND submsg_id=$2",
+                )
+                .bind(parent_msg)
+                .bind(submsg_id)
+                .fetch_all(&mut *transaction)
+                .await?,
+            );
+            sqlx::query("DELETE FROM attachments WHERE msgid=$1 AND submsg_id=$2")
+                .bind(parent_msg)
+                .bind(submsg_id)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query("DELETE FROM reports WHERE msgid=$1 AND submsg_id=$2")
+                .bind(parent_msg)
+                .bind(submsg_id)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query("DELETE FROM staff_notes WHERE msgid=$1 AND submsg_id=$2")
+                .bind(parent_msg)
+                .bind(submsg_id)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query("DELETE FROM submessages WHERE parent_msg=$1 AND submsg_id=$2")
+                .bind(parent_msg)
+                .bind(submsg_id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+        purge_attachments(&expired_attachments);
+        Ok(expired_msgs.len() + expired_submsgs.len())
+    }
+
+    /// Creates a new message (thread) along with its attachments in a single transaction.
+    /// If the board has more than `hard_limit` active threads afterwards, least active ones
+    /// are either archived or deleted. Returns ID of the new message.
+    pub async fn create_message(
+        &self,
+        post: &NewPost<'_>,
+        hard_limit: i64,
+        archive: bool,
+    ) -> Result<i64, sqlx::Error> {
+        let mut transaction = self.db_pool.begin().await?;
+
+        let msgid = insert_message(&mut transaction, post, false).await?;
+
+        let expired_attachments =
+            enforce_board_limit(&mut transaction, post.board, hard_limit, archive, post.time)
+                .await?;
+
+        transaction.commit().await?;
+        purge_attachments(&expired_attachments);
+        Ok(msgid)
+    }
+
+    /// Deletes archived messages that are older than `retention` seconds or don't fit
+    /// into `archive_limit` newest archived messages. Zero disables the respective check.
+    /// Returns number of deleted messages.
+    pub async fn prune_archive(
+        &self,
+        board: &str,
+        retention: i64,
+        archive_limit: i64,
+    ) -> Result<usize, sqlx::Error> {
+        let mut transaction = self.db_pool.begin().await?;
+        let mut expired = Vec::new();
+        if retention > 0 {
+            expired.extend(collect_ids(
+                sqlx::query("SELECT msgid FROM messages WHERE board=$1 AND archived_at < $2")
+                    .bind(board.to_string())
+                    .bind(crate::html_proc::since_epoch() - retention)
+                    .fetch_all(&mut *transaction)
+                    .await?,
+            )?);
+        }
+        if archive_limit > 0 {
+            expired.extend(collect_ids(sqlx::query("SELECT msgid FROM messages WHERE board=$1 AND archived_at IS NOT NULL AND msgid NOT IN (SELECT msgid FROM messages WHERE board=$1 AND archived_at IS NOT NULL ORDER BY archived_at DESC, msgid DESC LIMIT $2)")
+                .bind(board.to_string())
+                .bind(archive_limit)
+                .fetch_all(&mut *transaction)
+                .await?)?);
+        }
+        expired.sort_unstable();
+        expired.dedup();
+
+        let expired_attachments = delete_messages_in(&mut transaction, &expired).await?;
+        transaction.commit().await?;
+        purge_attachments(&expired_attachments);
+        Ok(expired.len())
+    }
+
+    /// Periodic cleanup: purging trash and pruning board archives
+    pub async fn run_maintenance(&self, config: &BoardConfig) {
+        match self
+            .purge_trash(config.trash_retention_days as i64 * 86400)
+            .await
+        {
+            Ok(0) => (),
+            Ok(n) => log::info!("Purged {} deleted posts from trash", n),
+            Err(e) => log::error!("Purging trash failure: {:?}", e),
+        };
+        // boards switched back to deletion can still have archived threads left to prune
+        for (board, settings) in config.boards.iter() {
+            match self
+                .prune_archive(
+                    board,
+                    settings.archive_retention_days as i64 * 86400,
+                    settings.archive_limit as i64,
+                )
+                .await
+            {
+                Ok(0) => (),
+                Ok(n) => log::info!("Pruned {} archived threads from /{}/", n, board),
+                Err(e) => log::error!("Pruning archive of /{}/ failure: {:?}", board, e),
+            };
+        }
+    }
+
+    /// Creates a new submessage in a single transaction. Submessage number is taken from
+    /// per-thread counter, so numbers of deleted submessages are never reused.
+    /// Parent message is bumped if `bump` is set, the submessage is public and there are
+    /// less than `bumplimit` submessages. Returns number of the new submessage.
+    pub async fn create_submessage(
+        &self,
+        parent_msg: i64,
+        post: &NewPost<'_>,
+        bump: bool,
+        bumplimit: i64,
+    ) -> Result<i64, sqlx::Error> {
+        let mut transaction = self.db_pool.begin().await?;
+
+        // incrementing the counter also locks parent message row until commit
+        let submsg_id: i64 = sqlx::query("UPDATE messages SET last_submsg_id = last_submsg_id + 1 WHERE msgid=$1 AND board=$2 AND archived_at IS NULL AND deleted_at IS NULL AND locked_at IS NULL RETURNING last_submsg_id")
+            .bind(parent_msg)
+            .bind(post.board.to_string())
+            .fetch_one(&mut *transaction)
+            .await?
+            .try_get(0)?;
+
+        let (held, shadowed) = post.hidden_flags();
+        sqlx::query("INSERT INTO submessages(parent_msg, submsg_id, board, time, author, submsg, poster_hash, held_at, shadowed_at, tripcode) VALUES ($1, $2, $3, $4, $5, $6, $7, CASE WHEN $8 = 1 THEN $4 END, CASE WHEN $9 = 1 THEN $4 END, $10)")
+            .bind(parent_msg)
+            .bind(submsg_id)
+            .bind(post.board.to_string())
+            .bind(post.time)
+            .bind(post.author.to_string())
+            .bind(post.body.to_string())
+            .bind(post.poster_hash.to_string())
+            .bind(held)
+            .bind(shadowed)
+            .bind(post.tripcode.to_string())
+            .execute(&mut *transaction)
+            .await?;
+        insert_attachments(&mut transaction, parent_msg, submsg_id, post.attachments).await?;
+
+        if bump && post.visibility == PostVisibility::Public {
+            sqlx::query("UPDATE messages SET latest_submsg=$1 WHERE msgid=$2 AND (SELECT COUNT(*) FROM submessages WHERE parent_msg=$2 AND deleted_at IS NULL AND held_at IS NULL AND shadowed_at IS NULL) <= $3")
+                .bind(post.time)
+                .bind(parent_msg)
+                .bind(bumplimit)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(submsg_id)
+    }
+
+    /// Inserts exported threads in a single transaction. Threads get new IDs, while replies
+    /// keep their numbers. After insertion, `rewrite_links` is called on every post text
+    /// with a map of old IDs to new ones; if it returns new text, the post is updated.
+    /// Returns the ID map.
+    pub async fn import_threads(
+        &self,
+        threads: &[ImportedThread<'_>],
+        rewrite_links: impl Fn(&str, &HashMap<i64, i64>) -> Option<String>,
+    ) -> Result<HashMap<i64, i64>, sqlx::Error> {
+        let mut transaction = self.db_pool.begin().await?;
+        let mut new_ids = HashMap::new();
+
+        for thread in threads {
+            let msgid: i64 = sqlx::query("INSERT INTO messages(board, time, author, msg, latest_submsg, last_submsg_id, archived_at, tripcode) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING msgid")
+                .bind(thread.post.board.to_string())
+                .bind(thread.post.time)
+                .bind(thread.post.author.to_string())
+                .bind(thread.post.body.to_string())
+                .bind(thread.latest_submsg)
+                .bind(thread.last_submsg_id)
+                .bind(thread.archived_at)
+                .bind(thread.post.tripcode.to_string())
+                .fetch_one(&mut *transaction)
+                .await?
+                .try_get(0)?;
+            insert_attachments(&mut transaction, msgid, 0, thread.post.attachments).await?;
+
+            for (submsg_id, reply) in thread.replies.iter() {
+                sqlx::query("INSERT INTO submessages(parent_msg, submsg_id, board, time, author, submsg, tripcode) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+                    .bind(msgid)
+                    .bind(submsg_id)
+                    .bind(reply.board.to_string())
+                    .bind(reply.time)
+                    .bind(reply.author.to_string())
+                    .bind(reply.body.to_string())
+                    .bind(reply.tripcode.to_string())
+                    .execute(&mut *transaction)
+                    .await?;
+                insert_attachments(&mut transaction, msgid, *submsg_id, reply.attachments).await?;
+            }
+            new_ids.insert(thread.old_msgid, msgid);
+        }
+
+        // links can point to threads that were inserted later, so they are fixed afterwards
+        for thread in threads {
+            let msgid = new_ids[&thread.old_msgid];
+            if let Some(text) = rewrite_links(thread.post.body, &new_ids) {
+                sqlx::query("UPDATE messages SET msg=$1 WHERE msgid=$2")
+                    .bind(text)
+                    .bind(msgid)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            for (submsg_id, reply) in thread.replies.iter() {
+                if let Some(text) = rewrite_links(reply.body, &new_ids) {
+                    sqlx::query(
+                        "UPDATE submessages SET submsg=$1 WHERE parent_msg=$2 AND submsg_id=$3",
+                    )
+                    .bind(text)
+                    .bind(msgid)
+                    .bind(submsg_id)
+                    .execute(&mut *transaction)
+                    .await?;
+                }
+            }
+        }
+
+        transaction.commit().await?;
+        Ok(new_ids)
+    }
 }
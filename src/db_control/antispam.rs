@@ -0,0 +1,45 @@
+//! Queries used by anti-spam checks of new posts. Deleted, held and shadowed posts are
+//! counted too, so that removing spam doesn't let the spammer post again right away.
+
+use super::DatabaseWrapper;
+use sqlx::Row;
+
+/// Max number of recent posts compared with a new one in near-duplicate checks
+const DUPLICATE_CHECK_LIMIT: i64 = 200;
+
+impl DatabaseWrapper {
+    /// Returns the time of the latest thread (or reply, if `thread` is false)
+    /// of a poster on a board, if there is any
+    pub async fn get_last_post_time(
+        &self,
+        poster_hash: &str,
+        board: &str,
+        thread: bool,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let table = if thread { "messages" } else { "submessages" };
+        let row = sqlx::query(&format!(
+            "SELECT time FROM {} WHERE poster_hash = $1 AND board = $2 ORDER BY time DESC LIMIT 1",
+            table
+        ))
+        .bind(poster_hash.to_string())
+        .bind(board.to_string())
+        .fetch_optional(&self.db_pool)
+        .await?;
+        row.map(|r| r.try_get(0)).transpose()
+    }
+
+    /// Returns texts of the latest threads and replies posted on a board since `since`, newest first
+    pub async fn get_recent_post_texts(
+        &self,
+        board: &str,
+        since: i64,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let rows = sqlx::query("SELECT msg AS text, time FROM messages WHERE board = $1 AND time > $2 UNION ALL SELECT submsg AS text, time FROM submessages WHERE board = $1 AND time > $2 ORDER BY time DESC LIMIT $3")
+            .bind(board.to_string())
+            .bind(since)
+            .bind(DUPLICATE_CHECK_LIMIT)
+            .fetch_all(&self.db_pool)
+            .await?;
+        rows.iter().map(|r| r.try_get(0)).collect()
+    }
+}
@@ -0,0 +1,61 @@
+//! Posts held for approval by board moderation policy, shown in the dashboard approval queue.
+//! Held posts have `held_at` set and are hidden from everyone until approved.
+
+use super::{DatabaseWrapper, MessageRow, SubmessageRow};
+
+impl DatabaseWrapper {
+    /// Returns held messages that weren't deleted, oldest first
+    pub async fn get_held_messages(&self) -> Result<Vec<MessageRow>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, MessageRow>(
+            "SELECT * FROM messages WHERE held_at IS NOT NULL AND deleted_at IS NULL ORDER BY held_at, msgid",
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+        self.with_message_attachments(rows).await
+    }
+
+    /// Returns held submessages of existing threads that weren't deleted, oldest first
+    pub async fn get_held_submessages(&self) -> Result<Vec<SubmessageRow>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, SubmessageRow>("SELECT * FROM submessages WHERE held_at IS NOT NULL AND deleted_at IS NULL AND parent_msg IN (SELECT msgid FROM messages WHERE deleted_at IS NULL) ORDER BY held_at, parent_msg, submsg_id")
+            .fetch_all(&self.db_pool)
+            .await?;
+        self.with_submessage_attachments(rows).await
+    }
+
+    /// Publishes a held post. Approved threads are bumped, so that they don't end up
+    /// buried under threads created while they were waiting.
+    /// Returns false if there is no such held post
+    pub async fn approve_post(&self, msgid: i64, submsg_id: i64) -> Result<bool, sqlx::Error> {
+        let result = match submsg_id {
+            0 => {
+                sqlx::query("UPDATE messages SET held_at = NULL, latest_submsg = $1 WHERE msgid = $2 AND held_at IS NOT NULL")
+                    .bind(crate::html_proc::since_epoch())
+                    .bind(msgid)
+                    .execute(&self.db_pool)
+                    .await?
+            }
+            _ => {
+                sqlx::query("UPDATE submessages SET held_at = NULL WHERE parent_msg = $1 AND submsg_id = $2 AND held_at IS NOT NULL")
+                    .bind(msgid)
+                    .bind(submsg_id)
+                    .execute(&self.db_pool)
+                    .await?
+            }
+        };
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Counts public posts of a poster on a board that weren't deleted
+    pub async fn count_approved_posts(
+        &self,
+        poster_hash: &str,
+        board: &str,
+    ) -> Result<i64, sqlx::Error> {
+        let row = sqlx::query("SELECT (SELECT COUNT(*) FROM messages WHERE poster_hash = $1 AND board = $2 AND deleted_at IS NULL AND held_at IS NULL AND shadowed_at IS NULL) + (SELECT COUNT(*) FROM submessages WHERE poster_hash = $1 AND board = $2 AND deleted_at IS NULL AND held_at IS NULL AND shadowed_at IS NULL)")
+            .bind(poster_hash.to_string())
+            .bind(board.to_string())
+            .fetch_one(&self.db_pool)
+            .await?;
+        sqlx::Row::try_get(&row, 0)
+    }
+}
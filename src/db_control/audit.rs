@@ -0,0 +1,248 @@
+//! Audit log of staff actions. Entries on posts keep a snapshot of their content,
+//! so that they stay readable after the posts are purged.
+
+use super::DatabaseWrapper;
+
+/// Maximum number of entries shown on a single audit log page
+pub const AUDIT_PAGE_SIZE: i64 = 200;
+
+/// Kinds of recorded staff actions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditAction {
+    Login,
+    Delete,
+    DeleteByPoster,
+    Approve,
+    Reject,
+    Restore,
+    Sticky,
+    Unsticky,
+    Lock,
+    Unlock,
+    Move,
+    SetNotice,
+    AddNote,
+    DismissReports,
+    Ban,
+    ShadowBan,
+    LiftBan,
+    CreateStaff,
+    RemoveStaff,
+    EditConfig,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 20] = [
+        AuditAction::Login,
+        AuditAction::Delete,
+        AuditAction::DeleteByPoster,
+        AuditAction::Approve,
+        AuditAction::Reject,
+        AuditAction::Restore,
+        AuditAction::Sticky,
+        AuditAction::Unsticky,
+        AuditAction::Lock,
+        AuditAction::Unlock,
+        AuditAction::Move,
+        AuditAction::SetNotice,
+        AuditAction::AddNote,
+        AuditAction::DismissReports,
+        AuditAction::Ban,
+        AuditAction::ShadowBan,
+        AuditAction::LiftBan,
+        AuditAction::CreateStaff,
+        AuditAction::RemoveStaff,
+        AuditAction::EditConfig,
+    ];
+
+    /// Name of the action, as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::Delete => "delete",
+            AuditAction::DeleteByPoster => "delete_by_poster",
+            AuditAction::Approve => "approve",
+            AuditAction::Reject => "reject",
+            AuditAction::Restore => "restore",
+            AuditAction::Sticky => "sticky",
+            AuditAction::Unsticky => "unsticky",
+            AuditAction::Lock => "lock",
+            AuditAction::Unlock => "unlock",
+            AuditAction::Move => "move",
+            AuditAction::SetNotice => "set_notice",
+            AuditAction::AddNote => "add_note",
+            AuditAction::DismissReports => "dismiss_reports",
+            AuditAction::Ban => "ban",
+            AuditAction::ShadowBan => "shadow_ban",
+            AuditAction::LiftBan => "lift_ban",
+            AuditAction::CreateStaff => "create_staff",
+            AuditAction::RemoveStaff => "remove_staff",
+            AuditAction::EditConfig => "edit_config",
+        }
+    }
+
+    /// Name of the action displayed on the dashboard
+    pub fn title(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "Logged in",
+            AuditAction::Delete => "Deleted post",
+            AuditAction::DeleteByPoster => "Deleted posts by poster",
+            AuditAction::Approve => "Approved post",
+            AuditAction::Reject => "Rejected post",
+            AuditAction::Restore => "Restored post",
+            AuditAction::Sticky => "Stickied thread",
+            AuditAction::Unsticky => "Unstickied thread",
+            AuditAction::Lock => "Locked thread",
+            AuditAction::Unlock => "Unlocked thread",
+            AuditAction::Move => "Moved thread",
+            AuditAction::SetNotice => "Set post notice",
+            AuditAction::AddNote => "Added staff note",
+            AuditAction::DismissReports => "Dismissed reports",
+            AuditAction::Ban => "Banned",
+            AuditAction::ShadowBan => "Shadow-banned",
+            AuditAction::LiftBan => "Lifted ban",
+            AuditAction::CreateStaff => "Created staff account",
+            AuditAction::RemoveStaff => "Removed staff account",
+            AuditAction::EditConfig => "Edited configuration",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AuditAction> {
+        AuditAction::ALL.into_iter().find(|a| a.as_str() == name)
+    }
+}
+
+/// Deserialized DB row containing an audit log entry. Entries that don't concern a post
+/// have `msgid` equal to 0; `target` describes other affected things (bans, accounts)
+#[allow(dead_code)]
+#[derive(Debug, sqlx::FromRow)]
+pub struct AuditRow {
+    pub entry_id: i64,
+    pub time: i64,
+    pub actor: String,
+    pub action: String,
+    pub board: String,
+    pub msgid: i64,
+    pub submsg_id: i64,
+    pub target: String,
+    pub snapshot: String,
+    pub reason: String,
+}
+
+/// Staff action that is about to be recorded. If `msgid` is set,
+/// board and snapshot are taken from the affected post
+pub struct NewAuditEntry<'a> {
+    pub actor: &'a str,
+    pub action: AuditAction,
+    pub board: &'a str,
+    pub msgid: i64,
+    pub submsg_id: i64,
+    pub target: &'a str,
+    pub reason: &'a str,
+}
+
+impl<'a> NewAuditEntry<'a> {
+    /// Entry for an action that doesn't concern a post
+    pub fn new(actor: &'a str, action: AuditAction) -> Self {
+        NewAuditEntry {
+            actor,
+            action,
+            board: "",
+            msgid: 0,
+            submsg_id: 0,
+            target: "",
+            reason: "",
+        }
+    }
+
+    /// Entry for an action on a message (`submsg_id` = 0) or submessage
+    pub fn on_post(actor: &'a str, action: AuditAction, msgid: i64, submsg_id: i64) -> Self {
+        NewAuditEntry {
+            msgid,
+            submsg_id,
+            ..NewAuditEntry::new(actor, action)
+        }
+    }
+}
+
+/// Conditions for audit log entries. Empty values (and `msgid` equal to 0) match everything
+pub struct AuditFilter<'a> {
+    pub actor: &'a str,
+    pub action: &'a str,
+    pub board: &'a str,
+    pub msgid: i64,
+}
+
+impl DatabaseWrapper {
+    /// Returns board of a post and a snapshot of its author and text, including deleted posts
+    async fn post_snapshot(
+        &self,
+        msgid: i64,
+        submsg_id: i64,
+    ) -> Result<(String, String), sqlx::Error> {
+        let row = if submsg_id == 0 {
+            sqlx::query("SELECT board, author, msg FROM messages WHERE msgid=$1")
+                .bind(msgid)
+                .fetch_one(&self.db_pool)
+                .await?
+        } else {
+            sqlx::query(
+                "SELECT board, author, submsg FROM submessages WHERE parent_msg=$1 AND submsg_id=$2",
+            )
+            .bind(msgid)
+            .bind(submsg_id)
+            .fetch_one(&self.db_pool)
+            .await?
+        };
+        let board: String = sqlx::Row::try_get(&row, 0)?;
+        let author: String = sqlx::Row::try_get(&row, 1)?;
+        let text: String = sqlx::Row::try_get(&row, 2)?;
+        Ok((board, format!("{}: {}", author, text)))
+    }
+
+    /// Records a staff action. Failures are logged, but don't interrupt the action itself
+    pub async fn log_action(&self, entry: &NewAuditEntry<'_>) {
+        let (board, snapshot) = if entry.msgid != 0 {
+            match self.post_snapshot(entry.msgid, entry.submsg_id).await {
+                Ok(s) => s,
+                Err(e) => {
+                    log::error!("Failed to get snapshot for audit log: {:?}", e);
+                    (entry.board.to_string(), String::new())
+                }
+            }
+        } else {
+            (entry.board.to_string(), String::new())
+        };
+        DatabaseWrapper::log_query_status(
+            sqlx::query("INSERT INTO audit_log(time, actor, action, board, msgid, submsg_id, target, snapshot, reason) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)")
+                .bind(crate::html_proc::since_epoch())
+                .bind(entry.actor.to_string())
+                .bind(entry.action.as_str())
+                .bind(board)
+                .bind(entry.msgid)
+                .bind(entry.submsg_id)
+                .bind(entry.target.to_string())
+                .bind(snapshot)
+                .bind(entry.reason.to_string())
+                .execute(&self.db_pool)
+                .await,
+            "Writing audit log entry",
+        );
+    }
+
+    /// Returns audit log entries matching a filter, newest first
+    pub async fn get_audit_log(
+        &self,
+        filter: &AuditFilter<'_>,
+        limit: i64,
+    ) -> Result<Vec<AuditRow>, sqlx::Error> {
+        sqlx::query_as::<_, AuditRow>("SELECT * FROM audit_log WHERE ($1 = '' OR actor = $1) AND ($2 = '' OR action = $2) AND ($3 = '' OR board = $3) AND ($4 = 0 OR msgid = $4) ORDER BY entry_id DESC LIMIT $5")
+            .bind(filter.actor.to_string())
+            .bind(filter.action.to_string())
+            .bind(filter.board.to_string())
+            .bind(filter.msgid)
+            .bind(limit)
+            .fetch_all(&self.db_pool)
+            .await
+    }
+}
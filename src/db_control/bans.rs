@@ -0,0 +1,122 @@
+//! Bans of posters, either by hashed IP address or by CIDR range.
+//! Raw addresses are never stored; single addresses are banned by their hash.
+
+use super::{DatabaseWrapper, NullableInt};
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+/// Deserialized DB row containing a ban. Exactly one of `ip_hash` and `ip_range` is set,
+/// the other one is empty. Empty `board` means that the ban applies to every board.
+/// Shadow bans (nonzero `shadow`) don't stop posting; posts are only shown to the poster instead.
+#[derive(Debug, sqlx::FromRow)]
+pub struct BanRow {
+    pub ban_id: i64,
+    pub ip_hash: String,
+    pub ip_range: String,
+    pub board: String,
+    pub reason: String,
+    pub created_at: i64,
+    #[sqlx(try_from = "NullableInt")]
+    pub expires_at: Option<i64>,
+    pub shadow: i64,
+}
+
+impl BanRow {
+    /// Checks whether the ban covers a poster with the given address and its hash
+    pub fn covers(&self, ip: &IpAddr, ip_hash: &str) -> bool {
+        if self.ip_range.is_empty() {
+            return self.ip_hash == ip_hash;
+        }
+        match self.ip_range.parse::<IpNet>() {
+            Ok(net) => net.contains(ip),
+            Err(_) => false,
+        }
+    }
+
+    /// Checks whether the ban hides posts of the poster instead of refusing them
+    pub fn is_shadow(&self) -> bool {
+        self.shadow != 0
+    }
+}
+
+/// Addresses a new ban applies to
+pub enum BanTarget {
+    Hash(String),
+    Range(IpNet),
+}
+
+impl DatabaseWrapper {
+    /// Returns all bans that haven't expired yet, newest first
+    pub async fn get_active_bans(&self) -> Result<Vec<BanRow>, sqlx::Error> {
+        sqlx::query_as::<_, BanRow>(
+            "SELECT * FROM bans WHERE expires_at IS NULL OR expires_at > $1 ORDER BY ban_id DESC",
+        )
+        .bind(crate::html_proc::since_epoch())
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Returns active bans covering a poster, longest ones first.
+    /// If `board` is set, only bans applying to that board are returned.
+    pub async fn find_bans(
+        &self,
+        board: Option<&str>,
+        ip: &IpAddr,
+        ip_hash: &str,
+    ) -> Result<Vec<BanRow>, sqlx::Error> {
+        let candidates = sqlx::query_as::<_, BanRow>("SELECT * FROM bans WHERE (expires_at IS NULL OR expires_at > $1) AND (ip_hash = $2 OR ip_range <> '') AND (board = '' OR board = $3 OR $3 = '') ORDER BY expires_at IS NULL DESC, expires_at DESC")
+            .bind(crate::html_proc::since_epoch())
+            .bind(ip_hash.to_string())
+            .bind(board.unwrap_or("").to_string())
+            .fetch_all(&self.db_pool)
+            .await?;
+        Ok(candidates
+            .into_iter()
+            .filter(|b| b.covers(ip, ip_hash))
+            .collect())
+    }
+
+    /// Stores a new ban. Empty `board` bans the poster on every board;
+    /// without `expires_at`, the ban is permanent. Returns ID of the new ban.
+    pub async fn create_ban(
+        &self,
+        target: &BanTarget,
+        board: &str,
+        reason: &str,
+        expires_at: Option<i64>,
+        shadow: bool,
+    ) -> Result<i64, sqlx::Error> {
+        let (ip_hash, ip_range) = match target {
+            BanTarget::Hash(h) => (h.clone(), String::new()),
+            BanTarget::Range(r) => (String::new(), r.trunc().to_string()),
+        };
+        // NULL is written as a literal, since `Any` driver binds `None` as INT4 in Postgres
+        let query = match expires_at {
+            Some(_) => "INSERT INTO bans(ip_hash, ip_range, board, reason, created_at, shadow, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING ban_id",
+            None => "INSERT INTO bans(ip_hash, ip_range, board, reason, created_at, shadow, expires_at) VALUES ($1, $2, $3, $4, $5, $6, NULL) RETURNING ban_id",
+        };
+        let mut query = sqlx::query(query)
+            .bind(ip_hash)
+            .bind(ip_range)
+            .bind(board.to_string())
+            .bind(reason.to_string())
+            .bind(crate::html_proc::since_epoch())
+            .bind(shadow as i64);
+        if let Some(e) = expires_at {
+            query = query.bind(e);
+        }
+        sqlx::Row::try_get(&query.fetch_one(&self.db_pool).await?, 0)
+    }
+
+    /// Lifts a ban by making it expire immediately, so that it stays on record
+    pub async fn lift_ban(&self, ban_id: i64) {
+        DatabaseWrapper::log_query_status(
+            sqlx::query("UPDATE bans SET expires_at=$1 WHERE ban_id=$2")
+                .bind(crate::html_proc::since_epoch())
+                .bind(ban_id)
+                .execute(&self.db_pool)
+                .await,
+            format!("Lifting ban {}", ban_id).as_str(),
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Copying of all data between databases, possibly with different backends.
+//! Rows keep their IDs; derived tables (like the search index) are filled by triggers.
+
+use super::{DatabaseKind, DatabaseWrapper};
+use futures::TryStreamExt;
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+
+/// Tables that are copied, parents first. Second value is the column
+/// backed by a Postgres sequence (`BIGSERIAL`), if there is one.
+pub const COPIED_TABLES: &[(&str, Option<&str>)] = &[
+    ("messages", Some("msgid")),
+    ("submessages", None),
+    ("attachments", Some("attachment_id")),
+    ("bans", Some("ban_id")),
+    ("reports", Some("report_id")),
+    ("staff", Some("staff_id")),
+    ("audit_log", Some("entry_id")),
+    ("staff_notes", Some("note_id")),
+];
+
+/// Value of any column, as read from the source database
+enum CopiedValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl sqlx::Type<sqlx::Any> for CopiedValue {
+    fn type_info() -> sqlx::any::AnyTypeInfo {
+        <String as sqlx::Type<sqlx::Any>>::type_info()
+    }
+
+    fn compatible(_ty: &sqlx::any::AnyTypeInfo) -> bool {
+        true
+    }
+}
+
+impl<'r> sqlx::Decode<'r, sqlx::Any> for CopiedValue {
+    fn decode(value: sqlx::any::AnyValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        let type_info = value.type_info();
+        Ok(match type_info.name() {
+            "NULL" => CopiedValue::Null,
+            "TEXT" => CopiedValue::Text(<String as sqlx::Decode<sqlx::Any>>::decode(value)?),
+            "REAL" | "DOUBLE" => {
+                CopiedValue::Real(<f64 as sqlx::Decode<sqlx::Any>>::decode(value)?)
+            }
+            "BOOLEAN" => {
+                CopiedValue::Integer(<bool as sqlx::Decode<sqlx::Any>>::decode(value)? as i64)
+            }
+            _ => CopiedValue::Integer(<i64 as sqlx::Decode<sqlx::Any>>::decode(value)?),
+        })
+    }
+}
+
+/// Row counts of a copied table in both databases
+pub struct CopiedTable {
+    pub table: &'static str,
+    pub source_rows: i64,
+    pub target_rows: i64,
+}
+
+impl DatabaseWrapper {
+    async fn count_rows(&self, table: &str) -> Result<i64, sqlx::Error> {
+        sqlx::query(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&self.db_pool)
+            .await?
+            .try_get(0)
+    }
+
+    /// Checks that none of the copied tables contain any rows
+    pub async fn is_empty(&self) -> Result<bool, sqlx::Error> {
+        for (table, _) in COPIED_TABLES {
+            if self.count_rows(table).await? > 0 {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Copies every row of `COPIED_TABLES` into `target` in a single transaction, keeping IDs.
+    /// Both databases must be at the same schema version. Postgres sequences of the target
+    /// are moved past the copied IDs. Returns row counts of both databases after copying.
+    pub async fn copy_into(
+        &self,
+        target: &DatabaseWrapper,
+    ) -> Result<Vec<CopiedTable>, sqlx::Error> {
+        let mut transaction = target.db_pool.begin().await?;
+
+        for (table, serial_column) in COPIED_TABLES {
+            let select_query = format!("SELECT * FROM {}", table);
+            let mut rows = sqlx::query(&select_query).fetch(&self.db_pool);
+            let mut copied = 0;
+            while let Some(row) = rows.try_next().await? {
+                let mut columns = Vec::new();
+                let mut values = Vec::new();
+                for (i, column) in row.columns().iter().enumerate() {
+                    columns.push(column.name());
+                    values.push(row.try_get::<CopiedValue, _>(i)?);
+                }
+                // NULLs are inlined, since `Any` driver binds them as INT4 regardless of column type
+                let mut placeholders = Vec::new();
+                let mut bound = 0;
+                for value in values.iter() {
+                    if let CopiedValue::Null = value {
+                        placeholders.push("NULL".to_string());
+                    } else {
+                        bound += 1;
+                        placeholders.push(format!("${}", bound));
+                    }
+                }
+                let insert_query = format!(
+                    "INSERT INTO {}({}) VALUES ({})",
+                    table,
+                    columns.join(", "),
+                    placeholders.join(", ")
+                );
+                let mut query = sqlx::query(&insert_query).persistent(false);
+                for value in values {
+                    query = match value {
+                        CopiedValue::Null => query,
+                        CopiedValue::Integer(v) => query.bind(v),
+                        CopiedValue::Real(v) => query.bind(v),
+                        CopiedValue::Text(v) => query.bind(v),
+                    };
+                }
+                query.execute(&mut *transaction).await?;
+                copied += 1;
+            }
+            log::info!("Copied {} rows of {}", copied, table);
+
+            if let (DatabaseKind::Postgres, Some(column)) = (target.kind, serial_column) {
+                sqlx::query(&format!(
+                    "SELECT setval(pg_get_serial_sequence('{0}', '{1}'), COALESCE((SELECT MAX({1}) FROM {0}), 0) + 1, false)",
+                    table, column
+                ))
+                .execute(&mut *transaction)
+                .await?;
+            }
+        }
+
+        transaction.commit().await?;
+
+        let mut counts = Vec::new();
+        for (table, _) in COPIED_TABLES {
+            counts.push(CopiedTable {
+                table,
+                source_rows: self.count_rows(table).await?,
+                target_rows: target.count_rows(table).await?,
+            });
+        }
+        Ok(counts)
+    }
+}
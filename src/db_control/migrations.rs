@@ -0,0 +1,194 @@
+//! Versioned schema migrations for SQLite and PostgreSQL databases.
+//! Migrations are embedded into the binary and applied in order;
+//! the latest applied version is recorded in `schema_version` table.
+
+use sqlx::{AnyConnection, Row};
+
+use super::NewAttachment;
+
+/// Data conversions that can't be expressed in plain SQL.
+/// They are run after the migration script, inside the same transaction.
+#[derive(Clone, Copy, Debug)]
+pub enum DataMigration {
+    /// Moves semicolon-separated `image` columns into `attachments` table
+    ImageColumnsToAttachments,
+}
+
+/// Single schema migration with separate scripts for each backend
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sqlite: &'static str,
+    pub postgres: &'static str,
+    pub data: Option<DataMigration>,
+}
+
+/// All known migrations, ordered by version. Never edit or reorder
+/// migrations that were already released; add a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sqlite: include_str!("../../migrations/sqlite/0001_initial.sql"),
+        postgres: include_str!("../../migrations/postgres/0001_initial.sql"),
+        data: None,
+    },
+    Migration {
+        version: 2,
+        description: "full-text search index",
+        sqlite: include_str!("../../migrations/sqlite/0002_full_text_search.sql"),
+        postgres: include_str!("../../migrations/postgres/0002_full_text_search.sql"),
+        data: None,
+    },
+    Migration {
+        version: 3,
+        description: "attachments table",
+        sqlite: include_str!("../../migrations/sqlite/0003_attachments.sql"),
+        postgres: include_str!("../../migrations/postgres/0003_attachments.sql"),
+        data: Some(DataMigration::ImageColumnsToAttachments),
+    },
+    Migration {
+        version: 4,
+        description: "drop legacy image columns",
+        sqlite: include_str!("../../migrations/sqlite/0004_drop_image_columns.sql"),
+        postgres: include_str!("../../migrations/postgres/0004_drop_image_columns.sql"),
+        data: None,
+    },
+    Migration {
+        version: 5,
+        description: "per-thread reply counter",
+        sqlite: include_str!("../../migrations/sqlite/0005_reply_counter.sql"),
+        postgres: include_str!("../../migrations/postgres/0005_reply_counter.sql"),
+        data: None,
+    },
+    Migration {
+        version: 6,
+        description: "thread archive",
+        sqlite: include_str!("../../migrations/sqlite/0006_thread_archive.sql"),
+        postgres: include_str!("../../migrations/postgres/0006_thread_archive.sql"),
+        data: None,
+    },
+    Migration {
+        version: 7,
+        description: "soft deletion",
+        sqlite: include_str!("../../migrations/sqlite/0007_soft_delete.sql"),
+        postgres: include_str!("../../migrations/postgres/0007_soft_delete.sql"),
+        data: None,
+    },
+    Migration {
+        version: 8,
+        description: "poster identities and bans",
+        sqlite: include_str!("../../migrations/sqlite/0008_bans.sql"),
+        postgres: include_str!("../../migrations/postgres/0008_bans.sql"),
+        data: None,
+    },
+    Migration {
+        version: 9,
+        description: "reports with reasons and comments",
+        sqlite: include_str!("../../migrations/sqlite/0009_reports.sql"),
+        postgres: include_str!("../../migrations/postgres/0009_reports.sql"),
+        data: None,
+    },
+    Migration {
+        version: 10,
+        description: "staff accounts",
+        sqlite: include_str!("../../migrations/sqlite/0010_staff.sql"),
+        postgres: include_str!("../../migrations/postgres/0010_staff.sql"),
+        data: None,
+    },
+    Migration {
+        version: 11,
+        description: "moderation audit log",
+        sqlite: include_str!("../../migrations/sqlite/0011_audit_log.sql"),
+        postgres: include_str!("../../migrations/postgres/0011_audit_log.sql"),
+        data: None,
+    },
+    Migration {
+        version: 12,
+        description: "sticky and locked threads",
+        sqlite: include_str!("../../migrations/sqlite/0012_thread_states.sql"),
+        postgres: include_str!("../../migrations/postgres/0012_thread_states.sql"),
+        data: None,
+    },
+    Migration {
+        version: 13,
+        description: "mod notices and staff notes on posts",
+        sqlite: include_str!("../../migrations/sqlite/0013_post_notes.sql"),
+        postgres: include_str!("../../migrations/postgres/0013_post_notes.sql"),
+        data: None,
+    },
+    Migration {
+        version: 14,
+        description: "post approval and shadow bans",
+        sqlite: include_str!("../../migrations/sqlite/0014_post_approval.sql"),
+        postgres: include_str!("../../migrations/postgres/0014_post_approval.sql"),
+        data: None,
+    },
+    Migration {
+        version: 15,
+        description: "tripcodes",
+        sqlite: include_str!("../../migrations/sqlite/0015_tripcodes.sql"),
+        postgres: include_str!("../../migrations/postgres/0015_tripcodes.sql"),
+        data: None,
+    },
+];
+
+/// Table used to keep track of applied migrations
+pub const VERSION_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_version (
+	version BIGINT PRIMARY KEY,
+	description TEXT NOT NULL,
+	applied_at BIGINT NOT NULL
+)";
+
+/// Returns version of the newest known migration
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Runs a data conversion step on the given connection
+pub async fn run_data_migration(
+    step: DataMigration,
+    conn: &mut AnyConnection,
+) -> Result<(), sqlx::Error> {
+    match step {
+        DataMigration::ImageColumnsToAttachments => image_columns_to_attachments(conn).await,
+    }
+}
+
+/// Splits legacy `image` values (like `data/user_images/1.png;data/user_images/2.jpg;`)
+/// and registers every file that still exists as an attachment
+async fn image_columns_to_attachments(conn: &mut AnyConnection) -> Result<(), sqlx::Error> {
+    let mut legacy_rows: Vec<(i64, i64, String)> = Vec::new();
+    for row in sqlx::query("SELECT msgid, image FROM messages")
+        .fetch_all(&mut *conn)
+        .await?
+    {
+        legacy_rows.push((row.try_get(0)?, 0, row.try_get(1)?));
+    }
+    for row in sqlx::query("SELECT parent_msg, submsg_id, image FROM submessages")
+        .fetch_all(&mut *conn)
+        .await?
+    {
+        legacy_rows.push((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?));
+    }
+
+    let mut converted = 0;
+    for (msgid, submsg_id, image) in legacy_rows {
+        let mut attachments = Vec::new();
+        for path in image.split(';').filter(|p| !p.is_empty()) {
+            let fs_path = std::path::Path::new(path);
+            let file_name = match fs_path.file_name() {
+                Some(n) => n.to_string_lossy().to_string(),
+                None => continue,
+            };
+            match NewAttachment::from_file(fs_path, &file_name) {
+                Some(attachment) => attachments.push(attachment),
+                None => log::error!("Skipping missing or unreadable media file: {}", path),
+            }
+        }
+        converted += attachments.len();
+        super::insert_attachments(&mut *conn, msgid, submsg_id, &attachments).await?;
+    }
+    log::info!("Converted {} media files into attachments", converted);
+    Ok(())
+}
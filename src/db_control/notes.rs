@@ -0,0 +1,103 @@
+//! Moderation notes on messages and submessages. The mod notice is shown to everyone
+//! under the post, staff notes are only shown on the dashboard.
+//! Notes on threads have `submsg_id` equal to 0.
+
+use super::DatabaseWrapper;
+use std::collections::HashMap;
+
+/// Deserialized DB row containing a private staff note
+#[allow(dead_code)]
+#[derive(Debug, sqlx::FromRow)]
+pub struct StaffNoteRow {
+    pub note_id: i64,
+    pub msgid: i64,
+    pub submsg_id: i64,
+    pub time: i64,
+    pub author: String,
+    pub note: String,
+}
+
+impl DatabaseWrapper {
+    /// Sets the public notice of a post. Empty notice removes it.
+    /// Returns false if there is no such post
+    pub async fn set_mod_notice(
+        &self,
+        msgid: i64,
+        submsg_id: i64,
+        notice: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = match submsg_id {
+            0 => {
+                sqlx::query("UPDATE messages SET mod_notice = $1 WHERE msgid = $2")
+                    .bind(notice.to_string())
+                    .bind(msgid)
+                    .execute(&self.db_pool)
+                    .await?
+            }
+            _ => sqlx::query(
+                "UPDATE submessages SET mod_notice = $1 WHERE parent_msg = $2 AND submsg_id = $3",
+            )
+            .bind(notice.to_string())
+            .bind(msgid)
+            .bind(submsg_id)
+            .execute(&self.db_pool)
+            .await?,
+        };
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Stores a private staff note on a post
+    pub async fn add_staff_note(
+        &self,
+        msgid: i64,
+        submsg_id: i64,
+        author: &str,
+        note: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO staff_notes(msgid, submsg_id, time, author, note) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(msgid)
+        .bind(submsg_id)
+        .bind(crate::html_proc::since_epoch())
+        .bind(author.to_string())
+        .bind(note.to_string())
+        .execute(&self.db_pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns all staff notes grouped by post (message ID and submessage number),
+    /// oldest notes first
+    pub async fn get_staff_notes(
+        &self,
+    ) -> Result<HashMap<(i64, i64), Vec<StaffNoteRow>>, sqlx::Error> {
+        let rows =
+            sqlx::query_as::<_, StaffNoteRow>("SELECT * FROM staff_notes ORDER BY time, note_id")
+                .fetch_all(&self.db_pool)
+                .await?;
+        let mut notes: HashMap<(i64, i64), Vec<StaffNoteRow>> = HashMap::new();
+        for row in rows {
+            notes
+                .entry((row.msgid, row.submsg_id))
+                .or_default()
+                .push(row);
+        }
+        Ok(notes)
+    }
+
+    /// Returns staff notes on a single post, oldest first
+    pub async fn get_post_staff_notes(
+        &self,
+        msgid: i64,
+        submsg_id: i64,
+    ) -> Result<Vec<StaffNoteRow>, sqlx::Error> {
+        sqlx::query_as::<_, StaffNoteRow>(
+            "SELECT * FROM staff_notes WHERE msgid=$1 AND submsg_id=$2 ORDER BY time, note_id",
+        )
+        .bind(msgid)
+        .bind(submsg_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+}
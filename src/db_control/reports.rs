@@ -0,0 +1,101 @@
+//! User reports on messages and submessages, shown in the dashboard report queue.
+//! Reports on threads have `submsg_id` equal to 0.
+
+use super::{DatabaseWrapper, MessageRow, SubmessageRow};
+use std::collections::HashMap;
+
+/// Deserialized DB row containing a single report
+#[allow(dead_code)]
+#[derive(Debug, sqlx::FromRow)]
+pub struct ReportRow {
+    pub report_id: i64,
+    pub msgid: i64,
+    pub submsg_id: i64,
+    pub reason: String,
+    pub comment: String,
+    pub reporter_hash: String,
+    pub time: i64,
+}
+
+impl DatabaseWrapper {
+    /// Stores a report. Repeated reports on the same post from the same reporter are ignored
+    pub async fn insert_report(
+        &self,
+        msgid: i64,
+        submsg_id: i64,
+        reason: &str,
+        comment: &str,
+        reporter_hash: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO reports(msgid, submsg_id, reason, comment, reporter_hash, time) SELECT $1, $2, $3, $4, $5, $6 WHERE NOT EXISTS (SELECT 1 FROM reports WHERE msgid=$1 AND submsg_id=$2 AND reporter_hash=$5)")
+            .bind(msgid)
+            .bind(submsg_id)
+            .bind(reason.to_string())
+            .bind(comment.to_string())
+            .bind(reporter_hash.to_string())
+            .bind(crate::html_proc::since_epoch())
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns all reports grouped by reported post (message ID and submessage number),
+    /// oldest reports first
+    pub async fn get_reports(&self) -> Result<HashMap<(i64, i64), Vec<ReportRow>>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, ReportRow>("SELECT * FROM reports ORDER BY time, report_id")
+            .fetch_all(&self.db_pool)
+            .await?;
+        let mut reports: HashMap<(i64, i64), Vec<ReportRow>> = HashMap::new();
+        for row in rows {
+            reports
+                .entry((row.msgid, row.submsg_id))
+                .or_default()
+                .push(row);
+        }
+        Ok(reports)
+    }
+
+    /// Returns reports on a single post, oldest first
+    pub async fn get_post_reports(
+        &self,
+        msgid: i64,
+        submsg_id: i64,
+    ) -> Result<Vec<ReportRow>, sqlx::Error> {
+        sqlx::query_as::<_, ReportRow>(
+            "SELECT * FROM reports WHERE msgid=$1 AND submsg_id=$2 ORDER BY time, report_id",
+        )
+        .bind(msgid)
+        .bind(submsg_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Returns reported messages that weren't deleted, most recently reported first
+    pub async fn get_flagged_messages(&self) -> Result<Vec<MessageRow>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, MessageRow>("SELECT * FROM messages WHERE EXISTS (SELECT 1 FROM reports WHERE reports.msgid = messages.msgid AND reports.submsg_id = 0) AND deleted_at IS NULL ORDER BY (SELECT MAX(time) FROM reports WHERE reports.msgid = messages.msgid AND reports.submsg_id = 0) DESC")
+            .fetch_all(&self.db_pool)
+            .await?;
+        self.with_message_attachments(rows).await
+    }
+
+    /// Returns reported submessages that weren't deleted (along with their threads),
+    /// most recently reported first
+    pub async fn get_flagged_submessages(&self) -> Result<Vec<SubmessageRow>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, SubmessageRow>("SELECT * FROM submessages WHERE EXISTS (SELECT 1 FROM reports WHERE reports.msgid = submessages.parent_msg AND reports.submsg_id = submessages.submsg_id) AND deleted_at IS NULL AND parent_msg IN (SELECT msgid FROM messages WHERE deleted_at IS NULL) ORDER BY (SELECT MAX(time) FROM reports WHERE reports.msgid = submessages.parent_msg AND reports.submsg_id = submessages.submsg_id) DESC")
+            .fetch_all(&self.db_pool)
+            .await?;
+        self.with_submessage_attachments(rows).await
+    }
+
+    /// Removes all reports on a post, taking it out of the report queue
+    pub async fn dismiss_reports(&self, msgid: i64, submsg_id: i64) {
+        DatabaseWrapper::log_query_status(
+            sqlx::query("DELETE FROM reports WHERE msgid=$1 AND submsg_id=$2")
+                .bind(msgid)
+                .bind(submsg_id)
+                .execute(&self.db_pool)
+                .await,
+            "Dismissing reports",
+        );
+    }
+}
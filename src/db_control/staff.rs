@@ -0,0 +1,220 @@
+//! Staff accounts used to access the admin dashboard, along with their roles and permissions
+
+use super::DatabaseWrapper;
+use argon2::password_hash::{
+    rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
+
+/// Actions on the dashboard that require a permission
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    /// Deleting posts and moderating threads (pinning and locking them)
+    Delete,
+    Ban,
+    ManageBoards,
+    ManageStaff,
+}
+
+/// Staff roles. Janitors can only act on boards listed in their account
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Admin,
+    GlobalModerator,
+    Janitor,
+}
+
+impl Role {
+    /// All roles, ordered from the most privileged one
+    pub const ALL: [Role; 3] = [Role::Admin, Role::GlobalModerator, Role::Janitor];
+
+    /// Name of the role, as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::GlobalModerator => "global_moderator",
+            Role::Janitor => "janitor",
+        }
+    }
+
+    /// Name of the role displayed on the dashboard
+    pub fn title(&self) -> &'static str {
+        match self {
+            Role::Admin => "Admin",
+            Role::GlobalModerator => "Global moderator",
+            Role::Janitor => "Janitor",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|r| r.as_str() == name)
+    }
+
+    /// Checks whether the role carries a permission
+    pub fn allows(&self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::GlobalModerator => matches!(permission, Permission::Delete | Permission::Ban),
+            Role::Janitor => permission == Permission::Delete,
+        }
+    }
+}
+
+/// Deserialized DB row containing a staff account.
+/// `boards` is a comma-separated list of boards janitors are assigned to
+#[allow(dead_code)]
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StaffRow {
+    pub staff_id: i64,
+    pub username: String,
+    pub password_hash: String,
+    pub role: String,
+    pub boards: String,
+    pub created_at: i64,
+}
+
+impl StaffRow {
+    /// Role of the account. Unknown roles are treated as the least privileged one
+    pub fn role(&self) -> Role {
+        Role::from_name(&self.role).unwrap_or(Role::Janitor)
+    }
+
+    /// Checks whether the account has a permission. If `board` is given, janitors
+    /// also need to be assigned to it; without it, they are allowed to act on their own boards
+    pub fn can(&self, permission: Permission, board: Option<&str>) -> bool {
+        let role = self.role();
+        if !role.allows(permission) {
+            return false;
+        }
+        match (role, board) {
+            (Role::Janitor, Some(b)) => self.boards.split(',').any(|assigned| assigned == b),
+            _ => true,
+        }
+    }
+}
+
+/// Argon2 hash of a discarded random password. Logins with unknown names are checked against it,
+/// so that they take as long as logins with existing ones
+pub const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$FeEFVa9aDtzAG0JfC2vycw$NF1cLkrHYCN/gJIHFExOC4ZDbNEOl2gpa4fQ/rKY+cc";
+
+/// Hashes a staff account password with Argon2id and a random salt, in PHC string format
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("Argon2 hashing with default parameters can't fail")
+        .to_string()
+}
+
+/// Checks whether a hash is an unsalted SHA-256 one, as stored by earlier versions
+pub fn is_legacy_hash(password_hash: &str) -> bool {
+    !password_hash.starts_with('$')
+}
+
+/// Checks a password against a stored hash, either an Argon2 or a legacy SHA-256 one
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    if is_legacy_hash(password_hash) {
+        return sha256::digest(password) == password_hash;
+    }
+    match PasswordHash::new(password_hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(e) => {
+            log::error!("Malformed password hash: {}", e);
+            false
+        }
+    }
+}
+
+impl DatabaseWrapper {
+    pub async fn get_staff(&self, staff_id: i64) -> Result<StaffRow, sqlx::Error> {
+        sqlx::query_as::<_, StaffRow>("SELECT * FROM staff WHERE staff_id=$1")
+            .bind(staff_id)
+            .fetch_one(&self.db_pool)
+            .await
+    }
+
+    pub async fn get_staff_by_name(&self, username: &str) -> Result<StaffRow, sqlx::Error> {
+        sqlx::query_as::<_, StaffRow>("SELECT * FROM staff WHERE username=$1")
+            .bind(username.to_string())
+            .fetch_one(&self.db_pool)
+            .await
+    }
+
+    pub async fn get_all_staff(&self) -> Result<Vec<StaffRow>, sqlx::Error> {
+        sqlx::query_as::<_, StaffRow>("SELECT * FROM staff ORDER BY staff_id")
+            .fetch_all(&self.db_pool)
+            .await
+    }
+
+    /// Creates a staff account. Returns ID of the new account
+    pub async fn create_staff(
+        &self,
+        username: &str,
+        password_hash: &str,
+        role: Role,
+        boards: &str,
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::Row::try_get(
+            &sqlx::query("INSERT INTO staff(username, password_hash, role, boards, created_at) VALUES ($1, $2, $3, $4, $5) RETURNING staff_id")
+                .bind(username.to_string())
+                .bind(password_hash.to_string())
+                .bind(role.as_str())
+                .bind(boards.to_string())
+                .bind(crate::html_proc::since_epoch())
+                .fetch_one(&self.db_pool)
+                .await?,
+            0,
+        )
+    }
+
+    /// Removes a staff account. The last admin account can't be removed
+    pub async fn remove_staff(&self, staff_id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM staff WHERE staff_id=$1 AND (role <> 'admin' OR (SELECT COUNT(*) FROM staff WHERE role='admin') > 1)")
+            .bind(staff_id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn set_password_hash(
+        &self,
+        staff_id: i64,
+        password_hash: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE staff SET password_hash=$1 WHERE staff_id=$2")
+            .bind(password_hash.to_string())
+            .bind(staff_id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Creates the initial `admin` account with `admin_password` from config if there are
+    /// no staff accounts yet. If `reset` is set, password of an existing `admin` account
+    /// is replaced as well
+    pub async fn ensure_admin_account(
+        &self,
+        password_hash: &str,
+        reset: bool,
+    ) -> Result<(), sqlx::Error> {
+        match self.get_staff_by_name("admin").await {
+            Ok(admin) if reset => {
+                self.set_password_hash(admin.staff_id, password_hash)
+                    .await?;
+                log::info!("Password of admin account was reset");
+            }
+            Ok(_) => (),
+            Err(sqlx::Error::RowNotFound) if self.get_all_staff().await?.is_empty() => {
+                self.create_staff("admin", password_hash, Role::Admin, "")
+                    .await?;
+                log::info!("Created admin account");
+            }
+            Err(sqlx::Error::RowNotFound) => (),
+            Err(e) => return Err(e),
+        };
+        Ok(())
+    }
+}
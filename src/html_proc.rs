@@ -9,16 +9,51 @@ use regex::Regex;
 use serde_json::json;
 use std::fs::read_to_string;
 use std::str;
-
-use crate::db_control::{MessageRow, SubmessageRow};
+use std::sync::Arc;
+
+use crate::banlist::BanlistEntry;
+use crate::db_control::audit::{AuditAction, AuditFilter, AuditRow};
+use crate::db_control::bans::BanRow;
+use crate::db_control::notes::StaffNoteRow;
+use crate::db_control::reports::ReportRow;
+use crate::db_control::staff::{Permission, Role, StaffRow};
+use crate::db_control::{
+    AttachmentRow, MessageRow, PosterSelection, SearchResultRow, SubmessageRow, SNIPPET_MATCH_END,
+    SNIPPET_MATCH_START,
+};
+use crate::routes::FileType;
+use crate::rule_file::PostField;
+use crate::wordfilter::{SharedWordfilters, WordfilterStage};
 use crate::BoardConfig;
 
+/// Scopes of poster cleanup, as named in dashboard forms
+pub const CLEANUP_SCOPES: [(&str, &str); 3] = [
+    ("thread", "Thread"),
+    ("board", "Board"),
+    ("site", "Whole site"),
+];
+
+/// Time windows of poster cleanup in hours, 0 meaning all time
+pub const CLEANUP_WINDOWS: [(i64, &str); 6] = [
+    (1, "Last hour"),
+    (6, "Last 6 hours"),
+    (24, "Last day"),
+    (168, "Last week"),
+    (720, "Last 30 days"),
+    (0, "All time"),
+];
+
+/// Preset public notices that can be attached to posts
+pub const MOD_NOTICES: [&str; 2] = [
+    "(USER WAS BANNED FOR THIS POST)",
+    "(USER WAS WARNED FOR THIS POST)",
+];
+
 /// Message types that can be formatted by `format_into_message`
 #[derive(PartialEq)]
 pub enum BoardMessageType {
     Message,        // messages on main page
     ParentMessage,  // parent message on topic pages
-    Submessage,     // submessages on topic pages
     CatalogMessage, // message blocks in board catalog
 }
 
@@ -38,20 +73,64 @@ pub fn since_epoch() -> i64 {
     }
 }
 
+/// Formats file size in bytes into human-readable string
+pub fn format_file_size(bytes: i64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Converts a ban into values displayed in templates
+fn ban_into_json(ban: &BanRow) -> serde_json::Value {
+    json!({
+        "ban_id": ban.ban_id,
+        "address": if ban.ip_range.is_empty() { &ban.ip_hash } else { &ban.ip_range },
+        "board": if ban.board.is_empty() { "All boards".to_string() } else { format!("/{}/", ban.board) },
+        "reason": ban.reason,
+        "shadow": ban.is_shadow(),
+        "issued": get_time(ban.created_at),
+        "expires": match ban.expires_at {
+            Some(t) => get_time(t),
+            None => "Never".to_string(),
+        },
+    })
+}
+
+/// Staff notes in the form passed to message templates
+fn staff_notes_json(notes: &[StaffNoteRow]) -> Vec<serde_json::Value> {
+    notes
+        .iter()
+        .map(|n| json!({"time": get_time(n.time), "author": n.author, "note": n.note}))
+        .collect()
+}
+
 /// Container for data necessary for formatting, such as chosen frontend directory,
 /// templating engine and a list of formatting regex
 pub struct HtmlFormatter<'a> {
     pub work_dir: String,
     handle: Handlebars<'a>,
     formatting_rules: IndexMap<String, String>,
+    wordfilters: Arc<SharedWordfilters>,
 }
 
 impl HtmlFormatter<'_> {
-    pub fn new(frontend_name: String) -> HtmlFormatter<'static> {
+    pub fn new(
+        frontend_name: String,
+        wordfilters: Arc<SharedWordfilters>,
+    ) -> HtmlFormatter<'static> {
         let mut obj = HtmlFormatter {
             work_dir: format!("./frontends/{}", frontend_name),
             handle: Handlebars::new(),
             formatting_rules: IndexMap::new(),
+            wordfilters,
         };
 
         let rules = match obj.load_rules() {
@@ -78,37 +157,33 @@ impl HtmlFormatter<'_> {
         Ok(raw_config)
     }
 
-    /// Turns string of file paths (separated by semicolons) into HTML image blocks
-    pub fn process_image_data(&self, images: &str, message_type: &BoardMessageType) -> String {
+    /// Turns message attachments into HTML image/video blocks
+    pub fn process_image_data(&self, attachments: &[AttachmentRow]) -> String {
         let mut image_container = String::new();
-        for image in images.split(';') {
-            let file_type = crate::routes::valid_file(image);
-            if file_type != crate::routes::FileType::Invalid {
-                let image_web_path: String = if message_type == &BoardMessageType::ParentMessage
-                    || message_type == &BoardMessageType::Submessage
-                {
-                    // descend several dirs if message is in topic (/{board}/topic/{number})
-                    format!("../../{}", &image[4..image.len()])
-                } else {
-                    format!("../{}", &image[4..image.len()])
-                };
-
-                let template_path = match file_type {
-                    crate::routes::FileType::Image => "templates/message_contents/image_block.html",
-                    crate::routes::FileType::Video => "templates/message_contents/video_block.html",
-                    _ => "templates/message_contents/image_block.html",
-                };
-
-                image_container.push_str(
-                    &self
-                        .handle
-                        .render_template(
-                            &self.get_file(template_path),
-                            &json!({ "img_link": image_web_path, "img_name": image[10..]}),
-                        )
-                        .unwrap(),
-                );
+        for attachment in attachments {
+            let template_path = match FileType::from_mime(&attachment.mime_type) {
+                FileType::Image => "templates/message_contents/image_block.html",
+                FileType::Video => "templates/message_contents/video_block.html",
+                FileType::Invalid => continue,
+            };
+
+            // file size and dimensions, like "1.5 MiB, 800x600"
+            let mut img_info = format_file_size(attachment.byte_size);
+            if let (Some(w), Some(h)) = (attachment.width, attachment.height) {
+                img_info.push_str(&format!(", {}x{}", w, h));
             }
+
+            image_container.push_str(
+                &self
+                    .handle
+                    .render_template(
+                        &self.get_file(template_path),
+                        &json!({ "img_link": format!("/user_images/{}", attachment.stored_name),
+                        "img_name": attachment.original_name,
+                        "img_info": img_info}),
+                    )
+                    .unwrap(),
+            );
         }
         image_container
     }
@@ -116,10 +191,10 @@ impl HtmlFormatter<'_> {
     /// Fits form data into submessage HTML template. Only accepts `SubmessageRow` structs.
     pub async fn format_into_submessage(&self, db_row: SubmessageRow) -> String {
         let msg = self.create_formatting(&db_row.submsg).await;
+        let msg = self.apply_render_filters(&db_row.board, &msg);
 
         // processing images
-        let images = db_row.image;
-        let image_container = self.process_image_data(&images, &BoardMessageType::Submessage);
+        let image_container = self.process_image_data(&db_row.attachments);
 
         let msg_contents = self
             .handle
@@ -135,8 +210,12 @@ impl HtmlFormatter<'_> {
                 &json!({"id": db_row.submsg_id,
                 "time": get_time(db_row.time),
                 "author": db_row.author,
+                "tripcode": db_row.tripcode,
+                "secure_tripcode": db_row.tripcode.starts_with("!!"),
                 "board": db_row.board,
                 "parent_id": db_row.parent_msg,
+                "mod_notice": db_row.mod_notice,
+                "staff_notes": staff_notes_json(&db_row.staff_notes),
                 "msg": msg_contents}),
             )
             .unwrap()
@@ -167,10 +246,10 @@ impl HtmlFormatter<'_> {
             };
         }
         msg = self.create_formatting(&msg).await;
+        msg = self.apply_render_filters(&db_row.board, &msg);
 
         // processing images/videos
-        let images = db_row.image;
-        let image_container = self.process_image_data(&images, &message_type);
+        let image_container = self.process_image_data(&db_row.attachments);
 
         let msg_contents = self
             .handle
@@ -182,6 +261,11 @@ impl HtmlFormatter<'_> {
 
         let time = get_time(db_row.time);
         let author = db_row.author;
+        let secure_tripcode = db_row.tripcode.starts_with("!!");
+        let archived = db_row.archived_at.is_some();
+        let sticky = db_row.stickied_at.is_some();
+        let locked = db_row.locked_at.is_some();
+        let staff_notes = staff_notes_json(&db_row.staff_notes);
 
         match message_type {
             BoardMessageType::Message => self
@@ -193,6 +277,12 @@ impl HtmlFormatter<'_> {
                 "time": time,
                 "page": page,
                 "author": author,
+                "tripcode": db_row.tripcode,
+                "secure_tripcode": secure_tripcode,
+                "sticky": sticky,
+                "locked": locked,
+                "mod_notice": db_row.mod_notice,
+                "staff_notes": staff_notes,
                 "msg": msg_contents}),
                 )
                 .unwrap(),
@@ -204,7 +294,14 @@ impl HtmlFormatter<'_> {
                 "time": time,
                 "page": page,
                 "author": author,
+                "tripcode": db_row.tripcode,
+                "secure_tripcode": secure_tripcode,
                 "id": id,
+                "archived": archived,
+                "sticky": sticky,
+                "locked": locked,
+                "mod_notice": db_row.mod_notice,
+                "staff_notes": staff_notes,
                 "msg": msg_contents}),
                 )
                 .unwrap(),
@@ -216,13 +313,37 @@ impl HtmlFormatter<'_> {
                 "time": time,
                 "board": db_row.board,
                 "page": page,
+                "sticky": sticky,
+                "locked": locked,
                 "msg": msg_contents}),
                 )
                 .unwrap(),
-            _ => String::new(),
         }
     }
 
+    /// Fits full-text search hit into search result template
+    pub async fn format_into_search_result(&self, db_row: SearchResultRow) -> String {
+        let idpair = match db_row.submsg_id {
+            0 => db_row.msgid.to_string(),
+            n => format!("{}.{}", db_row.msgid, n),
+        };
+        let snippet = handlebars::html_escape(&db_row.snippet)
+            .replace(SNIPPET_MATCH_START, "<mark>")
+            .replace(SNIPPET_MATCH_END, "</mark>");
+        // snippets are shown instead of messages, so render wordfilters apply to them as well
+        let snippet = self.apply_render_filters(&db_row.board, &snippet);
+        self.handle
+            .render_template(
+                &self.get_file("templates/message_blocks/search_result.html"),
+                &json!({"idpair": idpair,
+                "time": get_time(db_row.time),
+                "author": db_row.author,
+                "board": db_row.board,
+                "snippet": snippet}),
+            )
+            .unwrap()
+    }
+
     /// Formats board data into dashboard block
     pub async fn format_into_board_data(&self, data: Vec<(String, i64, i64, i64, i64)>) -> String {
         let mut res = String::new();
@@ -252,7 +373,8 @@ impl HtmlFormatter<'_> {
     ) -> String {
         // getting data about visited board
         let empty = String::from("");
-        let board_desc = acsim_config.boards.get(board_designation).unwrap();
+        let board_desc = &acsim_config.boards.get(board_designation).unwrap().description;
+        let settings = acsim_config.board_settings(board_designation).unwrap();
         let random_tagline = match acsim_config.taglines.choose(&mut rand::thread_rng()) {
             Some(s) => s,
             None => &empty,
@@ -276,6 +398,11 @@ impl HtmlFormatter<'_> {
                 "captcha_hash": captcha_hashed.unwrap_or(&"".to_string()),
                 "query_prev": query_prev,
                 "query_next": query_next,
+                "max_author_length": settings.max_author_length,
+                "max_message_length": settings.max_message_length,
+                "max_attachments": settings.max_attachments,
+                "allowed_media_types": settings.allowed_media_types.join(", "),
+                "require_image": settings.require_image,
                     }),
             )
             .unwrap()
@@ -305,33 +432,49 @@ impl HtmlFormatter<'_> {
     }
 
     pub async fn format_into_error(&self, error_code: actix_web::http::StatusCode) -> String {
+        self.format_into_error_with_reason(error_code, None).await
+    }
+
+    /// Formats the error page along with an explanation of what caused the error
+    pub async fn format_into_error_with_reason(
+        &self,
+        error_code: actix_web::http::StatusCode,
+        reason: Option<&str>,
+    ) -> String {
         self.handle
             .render_template(
                 &self.get_file("web_data/error.html"),
-                &json!({"error_id": error_code.as_u16(), "error_desc": error_code.to_string()[4..]}),
+                &json!({"error_id": error_code.as_u16(), "error_desc": error_code.to_string()[4..], "error_reason": reason}),
             )
             .unwrap()
     }
 
-    /// Formats data into `topic.html` (topic pages)
+    /// Formats data into `topic.html` (topic pages).
+    /// Reply form is only displayed if `captcha_hash` is present
     pub async fn format_into_topic(
         &self,
-        site_name: &String,
+        acsim_config: &BoardConfig,
         topic_number: &String,
         head_message: &String,
         submessages: &String,
         board_designation: &String,
         captcha_hash: Option<&String>,
     ) -> String {
+        let settings = acsim_config.board_settings(board_designation).unwrap();
         self.handle
             .render_template(
                 &self.get_file("web_data/topic.html"),
-                &json!({"site_name": site_name,
+                &json!({"site_name": acsim_config.site_name,
             "board_designation": board_designation,
             "topic_number": topic_number,
             "head_message": head_message,
             "submessages": submessages,
-            "captcha_hash": captcha_hash.unwrap_or(&"".to_string())}),
+            "captcha_hash": captcha_hash.unwrap_or(&"".to_string()),
+            "replies_closed": captcha_hash.is_none(),
+            "max_author_length": settings.max_author_length,
+            "max_message_length": settings.max_message_length,
+            "max_attachments": settings.max_attachments,
+            "allowed_media_types": settings.allowed_media_types.join(", ")}),
             )
             .unwrap()
     }
@@ -341,12 +484,34 @@ impl HtmlFormatter<'_> {
         &self,
         board_designation: &String,
         message_blocks: &String,
+        search_summary: &String,
         query_data_prev: &String,
         query_data_next: &String,
     ) -> String {
         self.handle
             .render_template(
                 &self.get_file("web_data/catalog.html"),
+                &json!({"board_designation": board_designation,
+                    "message_blocks": message_blocks,
+                    "search_summary": search_summary,
+                    "query_data_prev": query_data_prev,
+                    "query_data_next": query_data_next,
+                }),
+            )
+            .unwrap()
+    }
+
+    /// Formats data into board archive pages
+    pub async fn format_into_archive(
+        &self,
+        board_designation: &String,
+        message_blocks: &String,
+        query_data_prev: &String,
+        query_data_next: &String,
+    ) -> String {
+        self.handle
+            .render_template(
+                &self.get_file("web_data/archive.html"),
                 &json!({"board_designation": board_designation,
                     "message_blocks": message_blocks,
                     "query_data_prev": query_data_prev,
@@ -363,6 +528,7 @@ impl HtmlFormatter<'_> {
         captcha_hash: String,
         id: i64,
         subid: Option<i64>,
+        reasons: &[String],
     ) -> String {
         let subid_opt = match subid {
             Some(v) => format!("<input type=\"hidden\" name=\"subid\" value=\"{}\"/>", v),
@@ -371,7 +537,7 @@ impl HtmlFormatter<'_> {
         self.handle
             .render_template(
                 &self.get_file("web_data/report.html"),
-                &json!({"backlink": backlink, "captcha_hash": captcha_hash, "id": id, "subid_opt": subid_opt}),
+                &json!({"backlink": backlink, "captcha_hash": captcha_hash, "id": id, "subid_opt": subid_opt, "reasons": reasons}),
             )
             .unwrap()
     }
@@ -381,17 +547,304 @@ impl HtmlFormatter<'_> {
         self.get_file("web_data/report_accepted.html")
     }
 
+    /// Formats the page shown after a post was held for approval on `board`
+    pub async fn format_into_awaiting_approval(&self, board: &str) -> String {
+        self.handle
+            .render_template(
+                &self.get_file("web_data/awaiting_approval.html"),
+                &json!({ "board": board }),
+            )
+            .unwrap()
+    }
+
     /// Loads the login page for admin dashboard
     pub async fn format_into_login(&self) -> String {
         self.get_file("web_data/login.html")
     }
 
+    /// Formats the page shown to banned posters
+    pub async fn format_into_ban_page(&self, bans: Vec<BanRow>) -> String {
+        let bans: Vec<serde_json::Value> = bans.iter().map(ban_into_json).collect();
+        self.handle
+            .render_template(
+                &self.get_file("web_data/banned.html"),
+                &json!({ "bans": bans }),
+            )
+            .unwrap()
+    }
+
+    /// Formats the list of active bans and the ban form for admin dashboard.
+    /// `address` and `board` are put into the form in advance
+    pub async fn format_into_ban_list(
+        &self,
+        bans: Vec<BanRow>,
+        boards: Vec<&String>,
+        address: &str,
+        board: &str,
+        reason: &str,
+        csrf_token: &str,
+    ) -> String {
+        let bans: Vec<serde_json::Value> = bans.iter().map(ban_into_json).collect();
+        let boards: Vec<serde_json::Value> = boards
+            .into_iter()
+            .map(|b| json!({"name": b, "selected": b == board}))
+            .collect();
+        self.handle
+            .render_template(
+                &self.get_file("templates/message_blocks/ban_list.html"),
+                &json!({"bans": bans, "boards": boards, "address": address, "reason": reason, "csrf_token": csrf_token}),
+            )
+            .unwrap()
+    }
+
+    /// Formats reports on a single post: their count, reasons and comments
+    pub async fn format_into_report_summary(&self, reports: &[ReportRow]) -> String {
+        let mut reason_counts: IndexMap<&str, usize> = IndexMap::new();
+        for r in reports {
+            *reason_counts.entry(r.reason.as_str()).or_default() += 1;
+        }
+        let reasons: Vec<String> = reason_counts
+            .iter()
+            .map(|(reason, count)| format!("{} ({})", reason, count))
+            .collect();
+        let reports: Vec<serde_json::Value> = reports
+            .iter()
+            .map(|r| json!({"time": get_time(r.time), "reason": r.reason, "comment": r.comment}))
+            .collect();
+        self.handle
+            .render_template(
+                &self.get_file("templates/message_blocks/report_summary.html"),
+                &json!({"count": reports.len(), "reasons": reasons.join(", "), "reports": reports}),
+            )
+            .unwrap()
+    }
+
     /// Formats the admin dashboard and relevant flagged messages
-    pub async fn format_into_dashboard(&self, flagged: String) -> String {
+    pub async fn format_into_dashboard(
+        &self,
+        flagged: String,
+        staff: &StaffRow,
+        csrf_token: &str,
+    ) -> String {
         self.handle
             .render_template(
                 &self.get_file("web_data/dashboard.html"),
-                &json!({"flagged_list": flagged}),
+                &json!({
+                    "flagged_list": flagged,
+                    "username": staff.username,
+                    "role": staff.role().title(),
+                    "can_ban": staff.can(Permission::Ban, None),
+                    "can_manage_boards": staff.can(Permission::ManageBoards, None),
+                    "can_manage_staff": staff.can(Permission::ManageStaff, None),
+                    "csrf_token": csrf_token,
+                }),
+            )
+            .unwrap()
+    }
+
+    /// Formats the forms for changing thread states and moving threads for admin dashboard
+    pub async fn format_into_thread_tools(&self, boards: Vec<&String>, csrf_token: &str) -> String {
+        self.handle
+            .render_template(
+                &self.get_file("templates/message_blocks/thread_tools.html"),
+                &json!({ "boards": boards, "csrf_token": csrf_token }),
+            )
+            .unwrap()
+    }
+
+    /// Formats banlist entries, the form for adding them and the regex tester for admin dashboard
+    pub async fn format_into_banlist(
+        &self,
+        entries: &[BanlistEntry],
+        boards: Vec<&String>,
+        test_pattern: &str,
+        test_text: &str,
+        test_result: Option<&str>,
+        csrf_token: &str,
+    ) -> String {
+        let entries: Vec<serde_json::Value> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                json!({
+                    "index": i,
+                    "pattern": e.pattern,
+                    "scope": e.scope.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join(", "),
+                    "boards": match e.boards.is_empty() {
+                        true => "All boards".to_string(),
+                        false => e.boards.iter().map(|b| format!("/{}/", b)).collect::<Vec<String>>().join(" "),
+                    },
+                })
+            })
+            .collect();
+        self.handle
+            .render_template(
+                &self.get_file("templates/message_blocks/banlist.html"),
+                &json!({
+                    "entries": entries,
+                    "boards": boards.into_iter().map(|b| format!("/{}/", b)).collect::<Vec<String>>().join(" "),
+                    "test_pattern": test_pattern,
+                    "test_text": test_text,
+                    "test_result": test_result,
+                    "csrf_token": csrf_token,
+                }),
+            )
+            .unwrap()
+    }
+
+    /// Formats audit log entries and the filter form (prefilled with `filter`) for admin dashboard
+    pub async fn format_into_audit_log(
+        &self,
+        entries: Vec<AuditRow>,
+        boards: Vec<&String>,
+        filter: &AuditFilter<'_>,
+    ) -> String {
+        let entries: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|e| {
+                json!({
+                    "time": get_time(e.time),
+                    "actor": e.actor,
+                    "action": AuditAction::from_name(&e.action).map(|a| a.title()).unwrap_or(&e.action),
+                    "board": if e.board.is_empty() { String::new() } else { format!("/{}/", e.board) },
+                    "post": match (e.msgid, e.submsg_id) {
+                        (0, _) => String::new(),
+                        (msgid, 0) => msgid.to_string(),
+                        (msgid, submsg_id) => format!("{}.{}", msgid, submsg_id),
+                    },
+                    "target": e.target,
+                    "snapshot": e.snapshot,
+                    "reason": e.reason,
+                })
+            })
+            .collect();
+        let actions: Vec<serde_json::Value> = AuditAction::ALL
+            .iter()
+            .map(|a| json!({"name": a.as_str(), "title": a.title(), "selected": a.as_str() == filter.action}))
+            .collect();
+        let boards: Vec<serde_json::Value> = boards
+            .into_iter()
+            .map(|b| json!({"name": b, "selected": b == filter.board}))
+            .collect();
+        self.handle
+            .render_template(
+                &self.get_file("templates/message_blocks/audit_log.html"),
+                &json!({
+                    "entries": entries,
+                    "actions": actions,
+                    "boards": boards,
+                    "actor": filter.actor,
+                    "msgid": if filter.msgid == 0 { String::new() } else { filter.msgid.to_string() },
+                }),
+            )
+            .unwrap()
+    }
+
+    /// Formats the poster cleanup form (prefilled with `selection`) for admin dashboard,
+    /// along with either the number of posts that would be deleted or why they can't be
+    pub async fn format_into_cleanup(
+        &self,
+        boards: Vec<&String>,
+        scope: &str,
+        selection: &PosterSelection,
+        hours: i64,
+        preview: Option<Result<(i64, i64), String>>,
+        csrf_token: &str,
+    ) -> String {
+        let scopes: Vec<serde_json::Value> = CLEANUP_SCOPES
+            .iter()
+            .map(|(name, title)| json!({"name": name, "title": title, "selected": *name == scope}))
+            .collect();
+        let windows: Vec<serde_json::Value> = CLEANUP_WINDOWS
+            .iter()
+            .map(|(h, title)| json!({"hours": h, "title": title, "selected": *h == hours}))
+            .collect();
+        let boards: Vec<serde_json::Value> = boards
+            .into_iter()
+            .map(|b| json!({"name": b, "selected": *b == selection.board}))
+            .collect();
+        let (counts, error) = match preview {
+            Some(Ok((threads, replies))) => (
+                Some(json!({"threads": threads, "replies": replies, "total": threads + replies})),
+                None,
+            ),
+            Some(Err(e)) => (None, Some(e)),
+            None => (None, None),
+        };
+        self.handle
+            .render_template(
+                &self.get_file("templates/message_blocks/cleanup.html"),
+                &json!({
+                    "scopes": scopes,
+                    "windows": windows,
+                    "boards": boards,
+                    "scope": scope,
+                    "poster": selection.poster_hash,
+                    "board": selection.board,
+                    "msgid": if selection.msgid == 0 { String::new() } else { selection.msgid.to_string() },
+                    "hours": hours,
+                    "counts": counts,
+                    "error": error,
+                    "csrf_token": csrf_token,
+                }),
+            )
+            .unwrap()
+    }
+
+    /// Formats forms changing the public notice of a post and adding staff notes to it
+    pub async fn format_into_post_notes(
+        &self,
+        msgid: i64,
+        submsgid: Option<i64>,
+        mod_notice: &str,
+        csrf_token: &str,
+    ) -> String {
+        let presets: Vec<serde_json::Value> = MOD_NOTICES
+            .iter()
+            .map(|n| json!({"notice": n, "selected": *n == mod_notice}))
+            .collect();
+        let custom = !mod_notice.is_empty() && !MOD_NOTICES.contains(&mod_notice);
+        self.handle
+            .render_template(
+                &self.get_file("templates/message_blocks/post_notes.html"),
+                &json!({
+                    "msgid": msgid,
+                    "submsgid": submsgid,
+                    "presets": presets,
+                    "custom": custom,
+                    "mod_notice": mod_notice,
+                    "csrf_token": csrf_token,
+                }),
+            )
+            .unwrap()
+    }
+
+    /// Formats the list of staff accounts and the account creation form for admin dashboard
+    pub async fn format_into_staff_list(
+        &self,
+        accounts: Vec<StaffRow>,
+        csrf_token: &str,
+    ) -> String {
+        let accounts: Vec<serde_json::Value> = accounts
+            .iter()
+            .map(|a| {
+                json!({
+                    "staff_id": a.staff_id,
+                    "username": a.username,
+                    "role": a.role().title(),
+                    "boards": a.boards.split(',').filter(|b| !b.is_empty()).map(|b| format!("/{}/", b)).collect::<Vec<String>>().join(" "),
+                    "created": get_time(a.created_at),
+                })
+            })
+            .collect();
+        let roles: Vec<serde_json::Value> = Role::ALL
+            .iter()
+            .map(|r| json!({"name": r.as_str(), "title": r.title()}))
+            .collect();
+        self.handle
+            .render_template(
+                &self.get_file("templates/message_blocks/staff_list.html"),
+                &json!({"accounts": accounts, "roles": roles, "csrf_token": csrf_token}),
             )
             .unwrap()
     }
@@ -416,4 +869,11 @@ impl HtmlFormatter<'_> {
 
         result
     }
+
+    /// Applies render stage wordfilters of a board to formatted message HTML
+    fn apply_render_filters(&self, board: &str, msg: &str) -> String {
+        self.wordfilters
+            .get()
+            .apply(board, WordfilterStage::Render, PostField::Message, msg)
+    }
 }
@@ -0,0 +1,69 @@
+//! Limits failed dashboard logins per client address. Clients that fail too many times
+//! are locked out for a while; failures are forgotten once the lockout period passes
+//! without new ones.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// Failed logins of a single client
+struct FailedLogins {
+    count: u32,
+    last_failure: i64,
+}
+
+/// Failed login counters of all clients
+pub struct LoginThrottle {
+    max_attempts: u32,
+    lockout_secs: i64,
+    failures: Mutex<HashMap<IpAddr, FailedLogins>>,
+}
+
+impl LoginThrottle {
+    /// Creates a throttle allowing `max_attempts` failures per `lockout_secs`.
+    /// Zero `max_attempts` disables it
+    pub fn new(max_attempts: u32, lockout_secs: i64) -> LoginThrottle {
+        LoginThrottle {
+            max_attempts,
+            lockout_secs,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns number of seconds left until the client is allowed to log in again,
+    /// or `None` if it isn't locked out
+    pub fn locked_for(&self, ip: &IpAddr) -> Option<i64> {
+        if self.max_attempts == 0 {
+            return None;
+        }
+        let failures = self.failures.lock().unwrap();
+        let entry = failures.get(ip)?;
+        let left = entry.last_failure + self.lockout_secs - crate::html_proc::since_epoch();
+        match entry.count >= self.max_attempts && left > 0 {
+            true => Some(left),
+            false => None,
+        }
+    }
+
+    /// Records a failed login. Returns true if the client is locked out because of it
+    pub fn record_failure(&self, ip: &IpAddr) -> bool {
+        if self.max_attempts == 0 {
+            return false;
+        }
+        let now = crate::html_proc::since_epoch();
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, f| now - f.last_failure < self.lockout_secs);
+        let entry = failures.entry(*ip).or_insert(FailedLogins {
+            count: 0,
+            last_failure: now,
+        });
+        entry.count += 1;
+        entry.last_failure = now;
+        entry.count == self.max_attempts
+    }
+
+    /// Forgets failures of a client after a successful login
+    pub fn clear(&self, ip: &IpAddr) {
+        self.failures.lock().unwrap().remove(ip);
+    }
+}
@@ -7,12 +7,19 @@ use indexmap::map::IndexMap;
 use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslMethod};
 use serde::Deserialize;
 use std::fs::read_to_string;
+use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
+mod antispam;
+mod banlist;
+mod board_transfer;
 mod db_control;
 mod html_proc;
+mod login_throttle;
 mod routes;
+mod rule_file;
+mod tripcode;
+mod wordfilter;
 
 /// Deserialized version of config.yaml file
 #[derive(Deserialize, Clone)]
@@ -30,8 +37,355 @@ pub struct BoardConfig {
     admin_password: String,
     site_name: String,
     site_frontend: String,
-    boards: IndexMap<String, String>,
+    boards: IndexMap<String, BoardSettings>,
     taglines: Vec<String>,
+    #[serde(default = "default_true")]
+    auto_migrate: bool,
+    #[serde(default = "default_db_max_connections")]
+    db_max_connections: u32,
+    #[serde(default = "default_db_acquire_timeout")]
+    db_acquire_timeout: u64,
+    #[serde(default = "default_sqlite_busy_timeout")]
+    sqlite_busy_timeout: u64,
+    #[serde(default = "default_true")]
+    sqlite_wal: bool,
+    #[serde(default = "default_trash_retention_days")]
+    trash_retention_days: u32,
+    #[serde(default = "default_max_message_length")]
+    max_message_length: usize,
+    #[serde(default = "default_max_author_length")]
+    max_author_length: usize,
+    #[serde(default = "default_max_attachments")]
+    max_attachments: usize,
+    #[serde(default = "default_allowed_media_types")]
+    allowed_media_types: Vec<String>,
+    #[serde(default = "default_poster_name")]
+    default_poster_name: String,
+    #[serde(default)]
+    require_image: bool,
+    #[serde(default)]
+    moderation: ModerationPolicy,
+    #[serde(default = "default_moderation_first_posts")]
+    moderation_first_posts: u32,
+    #[serde(default = "default_thread_cooldown_secs")]
+    thread_cooldown_secs: u32,
+    #[serde(default = "default_reply_cooldown_secs")]
+    reply_cooldown_secs: u32,
+    #[serde(default = "default_max_links")]
+    max_links: usize,
+    #[serde(default = "default_duplicate_window_secs")]
+    duplicate_window_secs: u32,
+    #[serde(default = "default_duplicate_similarity")]
+    duplicate_similarity: f64,
+    #[serde(default)]
+    flood_limit: u32,
+    #[serde(default = "default_flood_window_secs")]
+    flood_window_secs: u32,
+    #[serde(default)]
+    ip_hash_salt: String,
+    #[serde(default)]
+    tripcode_salt: String,
+    #[serde(default = "default_report_reasons")]
+    report_reasons: Vec<String>,
+    #[serde(default = "default_login_max_attempts")]
+    login_max_attempts: u32,
+    #[serde(default = "default_login_lockout_minutes")]
+    login_lockout_minutes: u32,
+    #[serde(default)]
+    session_cookie_same_site: CookieSameSite,
+    session_cookie_secure: Option<bool>,
+}
+
+/// `SameSite` attribute of the dashboard session cookie
+#[derive(Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    #[default]
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<CookieSameSite> for actix_web::cookie::SameSite {
+    fn from(value: CookieSameSite) -> Self {
+        match value {
+            CookieSameSite::Strict => actix_web::cookie::SameSite::Strict,
+            CookieSameSite::Lax => actix_web::cookie::SameSite::Lax,
+            CookieSameSite::None => actix_web::cookie::SameSite::None,
+        }
+    }
+}
+
+/// What happens to least active threads when a board reaches `hard_limit`
+#[derive(Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitPolicy {
+    #[default]
+    Delete,
+    Archive,
+}
+
+/// Which new posts are held for approval before they are shown on a board
+#[derive(Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationPolicy {
+    #[default]
+    Off,
+    Threads,
+    All,
+    /// First `moderation_first_posts` posts of every poster on the board
+    FirstPosts,
+}
+
+/// Settings of a single board. In config.yaml, a board can be described
+/// either by its description alone or by a map with additional settings
+#[derive(Deserialize, Clone)]
+#[serde(from = "BoardEntry")]
+pub struct BoardSettings {
+    description: String,
+    limit_policy: LimitPolicy,
+    archive_retention_days: u32,
+    archive_limit: u32,
+    overrides: BoardOverrides,
+}
+
+/// Per-board replacements for global posting limits; unset values fall back to global ones
+#[derive(Deserialize, Clone, Default)]
+pub struct BoardOverrides {
+    bumplimit: Option<u16>,
+    hard_limit: Option<u16>,
+    page_limit: Option<u16>,
+    max_message_length: Option<usize>,
+    max_author_length: Option<usize>,
+    max_attachments: Option<usize>,
+    allowed_media_types: Option<Vec<String>>,
+    default_poster_name: Option<String>,
+    require_image: Option<bool>,
+    moderation: Option<ModerationPolicy>,
+    moderation_first_posts: Option<u32>,
+    thread_cooldown_secs: Option<u32>,
+    reply_cooldown_secs: Option<u32>,
+    max_links: Option<usize>,
+    duplicate_window_secs: Option<u32>,
+    duplicate_similarity: Option<f64>,
+    flood_limit: Option<u32>,
+    flood_window_secs: Option<u32>,
+}
+
+/// Raw board entry, as written in config.yaml. Only exists while the config is parsed,
+/// so the size of the detailed variant doesn't matter
+#[derive(Deserialize)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+enum BoardEntry {
+    Description(String),
+    Detailed {
+        description: String,
+        #[serde(default)]
+        limit_policy: LimitPolicy,
+        #[serde(default)]
+        archive_retention_days: u32,
+        #[serde(default)]
+        archive_limit: u32,
+        #[serde(flatten)]
+        overrides: BoardOverrides,
+    },
+}
+
+impl From<BoardEntry> for BoardSettings {
+    fn from(entry: BoardEntry) -> Self {
+        match entry {
+            BoardEntry::Description(description) => BoardSettings {
+                description,
+                limit_policy: LimitPolicy::default(),
+                archive_retention_days: 0,
+                archive_limit: 0,
+                overrides: BoardOverrides::default(),
+            },
+            BoardEntry::Detailed {
+                description,
+                limit_policy,
+                archive_retention_days,
+                archive_limit,
+                overrides,
+            } => BoardSettings {
+                description,
+                limit_policy,
+                archive_retention_days,
+                archive_limit,
+                overrides,
+            },
+        }
+    }
+}
+
+/// Posting limits in effect on a board, after applying its overrides
+pub struct EffectiveBoardSettings {
+    pub bumplimit: u16,
+    pub hard_limit: u16,
+    pub page_limit: u16,
+    pub max_message_length: usize,
+    pub max_author_length: usize,
+    pub max_attachments: usize,
+    pub allowed_media_types: Vec<String>,
+    pub default_poster_name: String,
+    pub require_image: bool,
+    pub moderation: ModerationPolicy,
+    pub moderation_first_posts: u32,
+    pub thread_cooldown_secs: u32,
+    pub reply_cooldown_secs: u32,
+    pub max_links: usize,
+    pub duplicate_window_secs: u32,
+    pub duplicate_similarity: f64,
+    pub flood_limit: u32,
+    pub flood_window_secs: u32,
+}
+
+impl BoardConfig {
+    /// Resolves settings of `board`, or `None` if there is no such board
+    pub fn board_settings(&self, board: &str) -> Option<EffectiveBoardSettings> {
+        let overrides = &self.boards.get(board)?.overrides;
+        Some(EffectiveBoardSettings {
+            bumplimit: overrides.bumplimit.unwrap_or(self.bumplimit),
+            hard_limit: overrides.hard_limit.unwrap_or(self.hard_limit),
+            page_limit: overrides.page_limit.unwrap_or(self.page_limit),
+            max_message_length: overrides
+                .max_message_length
+                .unwrap_or(self.max_message_length),
+            max_author_length: overrides
+                .max_author_length
+                .unwrap_or(self.max_author_length),
+            max_attachments: overrides.max_attachments.unwrap_or(self.max_attachments),
+            allowed_media_types: overrides
+                .allowed_media_types
+                .clone()
+                .unwrap_or_else(|| self.allowed_media_types.clone()),
+            default_poster_name: overrides
+                .default_poster_name
+                .clone()
+                .unwrap_or_else(|| self.default_poster_name.clone()),
+            require_image: overrides.require_image.unwrap_or(self.require_image),
+            moderation: overrides.moderation.unwrap_or(self.moderation),
+            moderation_first_posts: overrides
+                .moderation_first_posts
+                .unwrap_or(self.moderation_first_posts),
+            thread_cooldown_secs: overrides
+                .thread_cooldown_secs
+                .unwrap_or(self.thread_cooldown_secs),
+            reply_cooldown_secs: overrides
+                .reply_cooldown_secs
+                .unwrap_or(self.reply_cooldown_secs),
+            max_links: overrides.max_links.unwrap_or(self.max_links),
+            duplicate_window_secs: overrides
+                .duplicate_window_secs
+                .unwrap_or(self.duplicate_window_secs),
+            duplicate_similarity: overrides
+                .duplicate_similarity
+                .unwrap_or(self.duplicate_similarity),
+            flood_limit: overrides.flood_limit.unwrap_or(self.flood_limit),
+            flood_window_secs: overrides
+                .flood_window_secs
+                .unwrap_or(self.flood_window_secs),
+        })
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_db_max_connections() -> u32 {
+    10
+}
+
+fn default_db_acquire_timeout() -> u64 {
+    30
+}
+
+fn default_sqlite_busy_timeout() -> u64 {
+    5000
+}
+
+fn default_trash_retention_days() -> u32 {
+    7
+}
+
+fn default_max_message_length() -> usize {
+    4000
+}
+
+fn default_max_author_length() -> usize {
+    250
+}
+
+fn default_max_attachments() -> usize {
+    5
+}
+
+fn default_allowed_media_types() -> Vec<String> {
+    routes::SUPPORTED_MEDIA_TYPES
+        .iter()
+        .map(|t| t.to_string())
+        .collect()
+}
+
+fn default_poster_name() -> String {
+    "Anonymous".to_string()
+}
+
+fn default_moderation_first_posts() -> u32 {
+    1
+}
+
+fn default_thread_cooldown_secs() -> u32 {
+    60
+}
+
+fn default_reply_cooldown_secs() -> u32 {
+    10
+}
+
+fn default_max_links() -> usize {
+    5
+}
+
+fn default_duplicate_window_secs() -> u32 {
+    600
+}
+
+fn default_duplicate_similarity() -> f64 {
+    0.9
+}
+
+fn default_flood_window_secs() -> u32 {
+    60
+}
+
+fn default_report_reasons() -> Vec<String> {
+    ["Spam", "Illegal content", "Off-topic", "Other"]
+        .iter()
+        .map(|r| r.to_string())
+        .collect()
+}
+
+fn default_login_max_attempts() -> u32 {
+    5
+}
+
+fn default_login_lockout_minutes() -> u32 {
+    15
+}
+
+/// Reads a password from standard input and prints its hash in the format
+/// expected by `admin_password`. Used by setup.sh
+fn print_password_hash() {
+    let mut password = String::new();
+    if let Err(e) = std::io::stdin().read_line(&mut password) {
+        eprintln!("Failed to read password: {}", e);
+        std::process::exit(1);
+    }
+    let password = password.trim_end_matches(['\r', '\n']);
+    println!("{}", db_control::staff::hash_password(password));
 }
 
 fn create_ssl_acceptor() -> SslAcceptorBuilder {
@@ -46,8 +400,70 @@ fn create_ssl_acceptor() -> SslAcceptorBuilder {
     builder
 }
 
+/// Copies all data into the (empty) database at `target_url`, migrating it first
+async fn copy_database(
+    source: &db_control::DatabaseWrapper,
+    config: &BoardConfig,
+    target_url: &str,
+) {
+    let target = match db_control::DatabaseWrapper::connect(target_url, config).await {
+        Ok(t) => t,
+        Err(e) => return log::error!("Failed to connect to target database: {}", e),
+    };
+    match target.schema_version().await {
+        Ok(v) if v > db_control::migrations::latest_version() => {
+            return log::error!(
+                "Target database schema version {} is newer than this ACSIM build supports",
+                v
+            )
+        }
+        Ok(_) => (),
+        Err(e) => return log::error!("Failed to read target database schema version: {}", e),
+    };
+    if let Err(e) = target.apply_migrations().await {
+        return log::error!("Failed to apply migrations to target database: {}", e);
+    }
+    match target.is_empty().await {
+        Ok(true) => (),
+        Ok(false) => return log::error!("Target database already contains data; refusing to copy"),
+        Err(e) => return log::error!("Failed to check target database: {}", e),
+    };
+
+    let counts = match source.copy_into(&target).await {
+        Ok(c) => c,
+        Err(e) => return log::error!("Copying failed, target database was left unchanged: {}", e),
+    };
+    let mut mismatched = false;
+    for c in counts {
+        if c.source_rows == c.target_rows {
+            log::info!("{}: {} rows", c.table, c.target_rows);
+        } else {
+            mismatched = true;
+            log::error!(
+                "{}: {} rows in source, but {} in target",
+                c.table,
+                c.source_rows,
+                c.target_rows
+            );
+        }
+    }
+    if mismatched {
+        log::error!(
+            "Row counts differ; the source database may have been written to during copying"
+        );
+    } else {
+        log::info!("Database copied successfully");
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // hashing passwords doesn't need any data, so it works before setup is finished
+    if std::env::args().nth(1).as_deref() == Some("hash-password") {
+        print_password_hash();
+        return Ok(());
+    }
+
     // Setting working directory
     let path_local = format!("{}/.local/share/acsim", std::env::var("HOME").unwrap());
 
@@ -74,8 +490,8 @@ async fn main() -> std::io::Result<()> {
     // overriding password in the config if relevant env var is present
     // doesn't work when using compile-time macros. don't try it
     let opt_override = std::env::var("ACSIM_PASS_OVERRIDE");
-    if opt_override.is_ok() {
-        raw_config.admin_password = sha256::digest(opt_override.unwrap());
+    if let Ok(password) = &opt_override {
+        raw_config.admin_password = db_control::staff::hash_password(password);
     }
 
     // starting the logger
@@ -100,6 +516,8 @@ async fn main() -> std::io::Result<()> {
         })
         // disabling handlebars logs (they clog up the file too much)
         .level_for("handlebars", log::LevelFilter::Info)
+        // same goes for Postgres notices ("relation already exists" and such)
+        .level_for("sqlx::postgres::notice", log::LevelFilter::Warn)
         .chain(std::io::stdout())
         .chain(fern::log_file("./data/acsim.log").unwrap())
         .apply();
@@ -120,23 +538,189 @@ async fn main() -> std::io::Result<()> {
         Err(_) => log::error!(".env file failed to load. What happened?"),
     };
 
+    // media types that can't be attached anyway are most likely typos
+    for board in raw_config.boards.keys() {
+        for media_type in raw_config
+            .board_settings(board)
+            .unwrap()
+            .allowed_media_types
+        {
+            if !routes::SUPPORTED_MEDIA_TYPES.contains(&media_type.as_str()) {
+                log::error!(
+                    "Unsupported media type {} is allowed on /{}/ and will be ignored",
+                    media_type,
+                    board
+                );
+            }
+        }
+    }
+
+    // browsers drop cookies that allow cross-site requests without being limited to HTTPS
+    if raw_config.session_cookie_same_site == CookieSameSite::None
+        && !raw_config
+            .session_cookie_secure
+            .unwrap_or(raw_config.use_https)
+    {
+        log::error!(
+            "session_cookie_same_site is 'none', but the cookie isn't secure; dashboard logins won't work"
+        );
+    }
+
+    if raw_config.ip_hash_salt.is_empty() {
+        log::error!(
+            "ip_hash_salt is not set; hashed IP addresses of posters can be easily reversed"
+        );
+    }
+
+    if raw_config.tripcode_salt.is_empty() {
+        log::error!("tripcode_salt is not set; secure tripcodes can be cracked like classic ones");
+    }
+
     let config = Arc::new(raw_config.clone());
     let frontend_name: String = config.site_frontend.clone();
 
     // creating db connection through DatabaseWrapper
-    let raw_client = db_control::DatabaseWrapper::new()
+    let raw_client = db_control::DatabaseWrapper::new(&raw_config)
         .await
         .expect("Critical: something went wrong during database connection");
-    let client = Arc::new(Mutex::new(raw_client));
+
+    let schema_version = raw_client
+        .schema_version()
+        .await
+        .expect("Critical: failed to read database schema version");
+    if schema_version > db_control::migrations::latest_version() {
+        panic!(
+            "Critical: database schema version {} is newer than this ACSIM build supports ({})",
+            schema_version,
+            db_control::migrations::latest_version()
+        );
+    }
+
+    // handling commands passed as arguments
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("migrate") => {
+            let new_version = raw_client
+                .apply_migrations()
+                .await
+                .expect("Critical: failed to apply database migrations");
+            log::info!("Database schema is now at version {}", new_version);
+            return Ok(());
+        }
+        Some("export") | Some("import") | Some("copy-database") if args.len() < 2 => {
+            log::error!("Usage: acsim export <file> [board...] | acsim import <file> | acsim copy-database <target url>");
+            return Ok(());
+        }
+        Some("export") | Some("import") | Some("copy-database") | None => (),
+        Some(other) => {
+            log::error!(
+                "Unknown command: {}. Available commands: migrate, export, import, copy-database",
+                other
+            );
+            return Ok(());
+        }
+    };
+
+    if config.auto_migrate {
+        raw_client
+            .apply_migrations()
+            .await
+            .expect("Critical: failed to apply database migrations");
+    } else if !raw_client
+        .pending_migrations()
+        .await
+        .expect("Critical: failed to read database schema version")
+        .is_empty()
+    {
+        panic!("Critical: database schema is outdated. Run `acsim migrate` to update it");
+    }
+
+    // these commands need an up-to-date schema, so they are handled after migrations
+    match args.first().map(String::as_str) {
+        Some("export") => {
+            let boards: Vec<String> = if args.len() > 2 {
+                args[2..].to_vec()
+            } else {
+                config.boards.keys().cloned().collect()
+            };
+            match board_transfer::export_boards(&raw_client, &boards, Path::new(&args[1])).await {
+                Ok(s) => log::info!(
+                    "Exported {} threads, {} replies and {} media files into {}",
+                    s.threads,
+                    s.replies,
+                    s.media_files,
+                    args[1]
+                ),
+                Err(e) => log::error!("Export failed: {}", e),
+            };
+            return Ok(());
+        }
+        Some("import") => {
+            match board_transfer::import_archive(&raw_client, &config, Path::new(&args[1])).await {
+                Ok(s) => log::info!(
+                    "Imported {} threads, {} replies and {} media files from {}",
+                    s.threads,
+                    s.replies,
+                    s.media_files,
+                    args[1]
+                ),
+                Err(e) => log::error!("Import failed: {}", e),
+            };
+            return Ok(());
+        }
+        Some("copy-database") => {
+            copy_database(&raw_client, &config, &args[1]).await;
+            return Ok(());
+        }
+        _ => (),
+    };
+
+    // staff accounts are stored in the database; the first one is created from config
+    raw_client
+        .ensure_admin_account(&config.admin_password, opt_override.is_ok())
+        .await
+        .expect("Critical: failed to create admin account");
+
+    let client = Arc::new(raw_client);
+
+    // periodic database maintenance (purging trash, pruning archives)
+    let maintenance_client = Arc::clone(&client);
+    let maintenance_config = Arc::clone(&config);
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            maintenance_client.run_maintenance(&maintenance_config).await;
+        }
+    });
+
+    // banned words are reloaded whenever the banlist file changes
+    let banlist = Arc::new(banlist::SharedBanlist::new(banlist::BANLIST_PATH.into()));
+    rule_file::watch(Arc::clone(&banlist));
+
+    // rewriting rules are reloaded the same way
+    let wordfilters = Arc::new(wordfilter::SharedWordfilters::new(
+        wordfilter::WORDFILTERS_PATH.into(),
+    ));
+    rule_file::watch(Arc::clone(&wordfilters));
 
     // creating html formatter
-    let formatter = Arc::new(html_proc::HtmlFormatter::new(frontend_name.clone()));
+    let formatter = Arc::new(html_proc::HtmlFormatter::new(
+        frontend_name.clone(),
+        Arc::clone(&wordfilters),
+    ));
 
     // creating application state
     let application_data = web::Data::new(routes::ApplicationState {
         db_client: Arc::clone(&client),
         formatter: Arc::clone(&formatter),
         config: Arc::clone(&config),
+        banlist: Arc::clone(&banlist),
+        wordfilters: Arc::clone(&wordfilters),
+        login_throttle: Arc::new(login_throttle::LoginThrottle::new(
+            config.login_max_attempts,
+            config.login_lockout_minutes as i64 * 60,
+        )),
     });
 
     // rate limiting
@@ -148,6 +732,8 @@ async fn main() -> std::io::Result<()> {
 
     // configuring and starting the server
     let cookie_key = actix_web::cookie::Key::generate();
+    let cookie_secure = config.session_cookie_secure.unwrap_or(config.use_https);
+    let cookie_same_site = config.session_cookie_same_site;
     let server = HttpServer::new(move || {
         App::new()
             .wrap(middleware::Logger::default())
@@ -161,7 +747,8 @@ async fn main() -> std::io::Result<()> {
                 .cookie_name(String::from("acsim-admin-cookie"))
                 .session_lifecycle(actix_session::config::BrowserSession::default())
                 .cookie_content_security(actix_session::config::CookieContentSecurity::Private)
-                .cookie_secure(false)
+                .cookie_secure(cookie_secure)
+                .cookie_same_site(cookie_same_site.into())
                 .cookie_http_only(true)
                 .build(),
             )
@@ -179,17 +766,36 @@ async fn main() -> std::io::Result<()> {
             .service(actix_files::Files::new("/captcha", "./data/captcha"))
             .service(routes::index::root)
             .service(routes::error::error_page)
+            .service(routes::bans::ban_page)
+            .service(routes::approval::awaiting_approval)
             .service(routes::disambiguation::to_msg)
             .service(routes::report::report_msg)
             .service(routes::report::report_process_captcha)
             .service(routes::dashboard::view_dashboard)
             .service(routes::dashboard::delete_msg)
+            .service(routes::dashboard::delete_by_poster)
+            .service(routes::dashboard::restore_msg)
+            .service(routes::dashboard::approve_post)
+            .service(routes::dashboard::set_post_notice)
+            .service(routes::dashboard::add_post_note)
+            .service(routes::dashboard::dismiss_reports)
+            .service(routes::dashboard::delete_and_ban)
+            .service(routes::dashboard::ban_poster)
+            .service(routes::dashboard::lift_ban)
+            .service(routes::dashboard::create_staff)
+            .service(routes::dashboard::remove_staff)
+            .service(routes::dashboard::set_thread_state)
+            .service(routes::dashboard::move_thread)
+            .service(routes::dashboard::add_banlist_entry)
+            .service(routes::dashboard::remove_banlist_entry)
+            .service(routes::dashboard::logout)
             .service(routes::dashboard::login_page)
             .service(routes::board::board)
             .service(routes::board::board_process_form)
             .service(routes::topic::topic)
             .service(routes::topic::topic_process_form)
             .service(routes::catalog::board_catalog)
+            .service(routes::archive::board_archive)
     });
 
     let bind_string = if config.bind_addr.contains(':') {
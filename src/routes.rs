@@ -1,17 +1,25 @@
 //! Common functions and structs
 //! used in handling user requests
 
+use crate::banlist::SharedBanlist;
 use crate::db_control;
 use crate::html_proc;
-use crate::BoardConfig;
+use crate::login_throttle::LoginThrottle;
+pub use crate::routes::error::Rejection;
+use crate::rule_file::PostField;
+use crate::wordfilter::{SharedWordfilters, WordfilterStage};
+use crate::{BoardConfig, EffectiveBoardSettings, ModerationPolicy};
 use actix_multipart::form::{tempfile::TempFile, text::Text, MultipartForm};
+use actix_web::{web, HttpRequest};
 use serde::Deserialize;
 use std::fmt;
-use std::path::Path;
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
+pub mod approval;
+pub mod archive;
+pub mod bans;
 pub mod board;
 pub mod catalog;
 pub mod dashboard;
@@ -80,6 +88,17 @@ impl QueryOptions {
     }
 }
 
+/// MIME types of all media files that can be attached to posts
+pub const SUPPORTED_MEDIA_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/bmp",
+    "video/mp4",
+    "video/webm",
+];
+
 /// File categories that can be sent by users
 #[derive(PartialEq)]
 pub enum FileType {
@@ -88,56 +107,148 @@ pub enum FileType {
     Invalid,
 }
 
-/// Container for essential parts of the web app, such as a database client and config file
-pub struct ApplicationState<'a> {
-    pub db_client: Arc<Mutex<db_control::DatabaseWrapper>>,
-    pub formatter: Arc<html_proc::HtmlFormatter<'a>>,
-    pub config: Arc<BoardConfig>,
+impl FileType {
+    /// Determines file category from MIME type detected by libmagic
+    pub fn from_mime(mime_type: &str) -> FileType {
+        match mime_type {
+            "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "image/bmp" => {
+                FileType::Image
+            }
+            "video/mp4" | "video/webm" => FileType::Video,
+            _ => FileType::Invalid,
+        }
+    }
 }
 
-/// Function for checking a string for banned words
-pub async fn contains_banned_words(checked: &str) -> bool {
-    // maybe i ought to replace some of those with include_str! TODO
-    // you know, to avoid opening and reading the file each time a message is sent
-    let check_lower = checked.to_lowercase();
-    let raw_banlist = std::fs::read_to_string("./data/banlist.yaml")
-        .unwrap_or_else(|_| panic!("Can't read ./data/banlist.yaml. Is it there?"));
-    let banlist: Vec<String> = serde_yaml::from_str(&raw_banlist).unwrap();
-    for word in banlist {
-        let reg = regex::Regex::new(&word).unwrap();
-        if reg.is_match(&check_lower) {
-            return true;
-        }
+/// Poster's address along with its salted hash. Only the hash is ever stored
+pub struct PosterIdentity {
+    pub ip: IpAddr,
+    pub hash: String,
+}
+
+impl PosterIdentity {
+    /// Identifies the client that sent a request (the same address the rate limiter uses)
+    pub fn from_request(req: &HttpRequest, salt: &str) -> Option<PosterIdentity> {
+        let ip = req.peer_addr()?.ip().to_canonical();
+        Some(PosterIdentity {
+            ip,
+            hash: hash_ip(&ip, salt),
+        })
     }
-    false
 }
 
-/// Validates images sent by users using libmagic
-pub fn valid_file(image: &str) -> FileType {
-    if image.is_empty() {
-        return FileType::Invalid;
+/// Hashes an IP address with the server salt
+pub fn hash_ip(ip: &IpAddr, salt: &str) -> String {
+    sha256::digest(format!("{}{}", salt, ip))
+}
+
+/// Poster hash of the visitor, used to show it its own shadowed posts.
+/// Empty if the visitor can't be identified
+pub fn viewer_hash(req: &HttpRequest, salt: &str) -> String {
+    PosterIdentity::from_request(req, salt)
+        .map(|p| p.hash)
+        .unwrap_or_default()
+}
+
+/// Checks whether the poster is banned on a board. Returns a redirect to the ban page
+/// (or to an error page if bans can't be checked), if posting should be refused.
+/// Otherwise, returns whether the poster is shadow-banned
+pub async fn check_bans(
+    client: &db_control::DatabaseWrapper,
+    board: &str,
+    poster: &PosterIdentity,
+) -> Result<bool, web::Redirect> {
+    match client
+        .find_bans(Some(board), &poster.ip, &poster.hash)
+        .await
+    {
+        Ok(bans) if bans.iter().all(|b| b.is_shadow()) => Ok(!bans.is_empty()),
+        Ok(_) => Err(web::Redirect::to("/banned")),
+        Err(e) => {
+            log::error!("Failed to check bans: {:?}", e);
+            Err(web::Redirect::to("/error?error_code=500"))
+        }
     }
+}
 
-    let mut image_fs_path = image.to_string();
-    image_fs_path = image_fs_path[..image_fs_path.len()].to_string(); // path ends with "\" for some reason
+/// Decides who is shown a new post: posts of shadow-banned posters are shadowed,
+/// others are held if the moderation policy of the board requires it
+pub async fn post_visibility(
+    client: &db_control::DatabaseWrapper,
+    settings: &EffectiveBoardSettings,
+    board: &str,
+    poster: &PosterIdentity,
+    new_thread: bool,
+    shadow_banned: bool,
+) -> Result<db_control::PostVisibility, sqlx::Error> {
+    if shadow_banned {
+        return Ok(db_control::PostVisibility::Shadowed);
+    }
+    let held = match settings.moderation {
+        ModerationPolicy::Off => false,
+        ModerationPolicy::Threads => new_thread,
+        ModerationPolicy::All => true,
+        ModerationPolicy::FirstPosts => {
+            client.count_approved_posts(&poster.hash, board).await?
+                < settings.moderation_first_posts as i64
+        }
+    };
+    Ok(match held {
+        true => db_control::PostVisibility::Held,
+        false => db_control::PostVisibility::Public,
+    })
+}
 
-    // libmagic image validation
-    let cookie = magic::Cookie::open(magic::cookie::Flags::ERROR).unwrap();
-    let database = Default::default();
-    let cookie = cookie.load(&database).unwrap();
-    let file_type = cookie.file(&image_fs_path);
+/// Container for essential parts of the web app, such as a database client and config file.
+/// Database client is shared without locking, since it is backed by a connection pool
+pub struct ApplicationState<'a> {
+    pub db_client: Arc<db_control::DatabaseWrapper>,
+    pub formatter: Arc<html_proc::HtmlFormatter<'a>>,
+    pub config: Arc<BoardConfig>,
+    pub banlist: Arc<SharedBanlist>,
+    pub wordfilters: Arc<SharedWordfilters>,
+    pub login_throttle: Arc<LoginThrottle>,
+}
 
-    if let Ok(raw_type) = file_type {
-        if Path::new(&image_fs_path).exists() {
-            if raw_type.contains("image data") {
-                return FileType::Image;
-            } else if raw_type.contains("MP4 Base Media") || raw_type.contains("WebM") {
-                return FileType::Video;
-            }
+/// Checks author name, message and names of attached files for banned words
+pub fn contains_banned_words(
+    banlist: &SharedBanlist,
+    board: &str,
+    author: &str,
+    message: &str,
+    files: &[TempFile],
+) -> bool {
+    let banlist = banlist.get();
+    let mut checked = vec![(PostField::Author, author), (PostField::Message, message)];
+    for f in files {
+        if let Some(name) = &f.file_name {
+            checked.push((PostField::Filename, name));
+        }
+    }
+    for (scope, text) in checked {
+        if let Some(entry) = banlist.find_match(board, scope, text) {
+            log::info!(
+                "Rejected post on /{}/: {} matches banned pattern '{}'",
+                board,
+                scope.as_str(),
+                entry.pattern
+            );
+            return true;
         }
     }
+    false
+}
 
-    FileType::Invalid
+/// Applies store stage wordfilters of a board to a part of a new post
+pub fn rewrite_words(
+    wordfilters: &SharedWordfilters,
+    board: &str,
+    scope: PostField,
+    text: &str,
+) -> String {
+    wordfilters
+        .get()
+        .apply(board, WordfilterStage::Store, scope, text)
 }
 
 /// Creates a captcha image, saves it to ./data/captcha and returns the characters it contains
@@ -207,14 +318,19 @@ pub async fn delete_captcha_image(captcha_hash: String) {
     }
 }
 
-/// Handler for files in multipart forms
-pub async fn process_files(files: &Vec<TempFile>) -> String {
-    let mut filepath_collection = String::from("");
+/// Handler for files in multipart forms. Moves valid media files of allowed types
+/// to `USER_IMAGES_DIR` and returns their metadata
+pub async fn process_files(
+    files: &[TempFile],
+    max_attachments: usize,
+    allowed_media_types: &[String],
+) -> Vec<db_control::NewAttachment> {
+    let mut attachments = Vec::new();
     for (i, item) in files.iter().enumerate() {
-        // only process the first 4 files, delete the rest
+        // only process the first `max_attachments` files, delete the rest
         let f = &item;
         let temp_file_path = f.file.path();
-        if i > 4 {
+        if i >= max_attachments {
             let remove_excess_status = std::fs::remove_file(temp_file_path);
             if remove_excess_status.is_err() {
                 log::error!(
@@ -224,18 +340,32 @@ pub async fn process_files(files: &Vec<TempFile>) -> String {
             }
             continue;
         }
-        // test to see if it is an actual image/video
-        if valid_file(temp_file_path.to_str().unwrap()) == FileType::Invalid {
-            continue;
-        }
-        let orig_name = f
+        let orig_name: String = f
             .file_name
-            .as_ref()
-            .expect("no file name")
-            .split('.')
-            .collect::<Vec<&str>>();
+            .clone()
+            .unwrap_or_else(|| "file".to_string())
+            .chars()
+            .take(255)
+            .collect();
+        // test to see if it is an actual image/video
+        let temp_metadata = match db_control::NewAttachment::from_file(temp_file_path, &orig_name) {
+            Some(m)
+                if FileType::from_mime(&m.mime_type) != FileType::Invalid
+                    && allowed_media_types.contains(&m.mime_type) =>
+            {
+                m
+            }
+            _ => continue,
+        };
+        // extension is derived from detected type, so that stored files are served correctly
+        let extension = temp_metadata.mime_type[6..].to_string(); // "image/png" -> "png"
         let new_name = rand::random::<u64>().to_string();
-        let new_filepath = PathBuf::from(format!("data/user_images/{}.{}", new_name, orig_name[1]));
+        let new_filepath = PathBuf::from(format!(
+            "{}/{}.{}",
+            db_control::USER_IMAGES_DIR,
+            new_name,
+            extension
+        ));
         let copy_status = std::fs::copy(temp_file_path, new_filepath.clone());
         let remove_status = std::fs::remove_file(temp_file_path);
 
@@ -245,13 +375,16 @@ pub async fn process_files(files: &Vec<TempFile>) -> String {
                 temp_file_path.display(),
                 &new_filepath.display()
             );
+            continue;
         }
         if remove_status.is_err() {
             log::error!("Failed to delete file: {}", temp_file_path.display());
         }
 
-        filepath_collection.push_str(new_filepath.to_str().unwrap());
-        filepath_collection.push(';');
+        attachments.push(db_control::NewAttachment {
+            stored_name: format!("{}.{}", new_name, extension),
+            ..temp_metadata
+        });
     }
-    filepath_collection
+    attachments
 }
@@ -0,0 +1,24 @@
+//! Handler for the page shown to posters whose posts were held for approval
+
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::routes::ApplicationState;
+
+/// Query param containing the board the post was sent to
+#[derive(serde::Deserialize)]
+struct ApprovalQuery {
+    board: String,
+}
+
+/// Tells the poster that the post will be shown once approved
+#[get("/awaiting_approval")]
+pub async fn awaiting_approval(
+    data: web::Data<ApplicationState<'_>>,
+    query: web::Query<ApprovalQuery>,
+) -> impl Responder {
+    let board = match data.config.boards.contains_key(&query.board) {
+        true => query.board.as_str(),
+        false => "",
+    };
+    HttpResponse::Ok().body(data.formatter.format_into_awaiting_approval(board).await)
+}
@@ -0,0 +1,68 @@
+//! Handler for board archives
+
+use actix_web::{get, http::StatusCode, web, HttpRequest, HttpResponse, Responder};
+
+use crate::html_proc;
+use crate::routes::ApplicationState;
+use crate::routes::PathInfo;
+use crate::routes::QueryOptions;
+
+/// Responder for lists of archived threads
+#[get("{board}/archive")]
+pub async fn board_archive(
+    data: web::Data<ApplicationState<'_>>,
+    page_data: web::Query<QueryOptions>,
+    info: web::Path<PathInfo>,
+    req: HttpRequest,
+) -> impl Responder {
+    let page_limit = match data.config.board_settings(&info.board) {
+        Some(s) => s.page_limit as i64,
+        None => {
+            return HttpResponse::Ok().body(
+                data.formatter
+                    .format_into_error(StatusCode::NOT_FOUND)
+                    .await,
+            )
+        }
+    };
+    let client = &data.db_client;
+    let viewer = crate::routes::viewer_hash(&req, &data.config.ip_hash_salt);
+    let mut inserted_msg = String::from("");
+
+    let mut current_page = page_data.page.unwrap_or(1);
+    if current_page <= 0 {
+        current_page = 1;
+    }
+
+    for row in client
+        .get_archived_messages(&info.board, current_page, page_limit, &viewer)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+    {
+        inserted_msg.push_str(
+            data.formatter
+                .format_into_message(
+                    html_proc::BoardMessageType::CatalogMessage,
+                    row,
+                    &current_page.to_string(),
+                    None,
+                )
+                .await
+                .as_str(),
+        );
+    }
+
+    let link_queries = page_data.into_inner().get_neighbour_pages();
+
+    HttpResponse::Ok().body(
+        data.formatter
+            .format_into_archive(
+                &info.board,
+                &inserted_msg,
+                &link_queries.0.to_string(),
+                &link_queries.1.to_string(),
+            )
+            .await,
+    )
+}
@@ -0,0 +1,25 @@
+//! Handler for the page shown to banned posters
+
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+
+use crate::routes::{ApplicationState, PosterIdentity};
+
+/// Lists bans covering the visitor on any board. Shadow bans are never shown
+#[get("/banned")]
+pub async fn ban_page(data: web::Data<ApplicationState<'_>>, req: HttpRequest) -> impl Responder {
+    let bans = match PosterIdentity::from_request(&req, &data.config.ip_hash_salt) {
+        Some(poster) => data
+            .db_client
+            .find_bans(None, &poster.ip, &poster.hash)
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Failed to check bans: {:?}", e);
+                Vec::new()
+            })
+            .into_iter()
+            .filter(|b| !b.is_shadow())
+            .collect(),
+        None => Vec::new(),
+    };
+    HttpResponse::Ok().body(data.formatter.format_into_ban_page(bans).await)
+}
@@ -1,10 +1,13 @@
 //! Handlers for boards
 
 use actix_multipart::form::MultipartForm;
-use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder};
+use actix_web::{get, http::StatusCode, post, web, HttpRequest, HttpResponse, Responder};
 
+use crate::antispam;
 use crate::html_proc;
 use crate::routes::*;
+use crate::rule_file::PostField;
+use crate::tripcode;
 
 /// Responder for boards
 #[get("/{board}")]
@@ -12,16 +15,20 @@ pub async fn board(
     data: web::Data<ApplicationState<'_>>,
     info: web::Path<PathInfo>,
     page_data: web::Query<QueryOptions>,
+    req: HttpRequest,
 ) -> impl Responder {
-    if !data.config.boards.contains_key(&info.board) {
-        // we will have to manually format and send the response
-        return HttpResponse::Ok().body(
-            data.formatter
-                .format_into_error(StatusCode::NOT_FOUND)
-                .await,
-        );
-    }
-    let client = data.db_client.lock().await;
+    let settings = match data.config.board_settings(&info.board) {
+        Some(s) => s,
+        None => {
+            // we will have to manually format and send the response
+            return HttpResponse::Ok().body(
+                data.formatter
+                    .format_into_error(StatusCode::NOT_FOUND)
+                    .await,
+            );
+        }
+    };
+    let client = &data.db_client;
     let mut inserted_msg = String::from("");
 
     let mut current_page = page_data.page.unwrap_or(1);
@@ -30,8 +37,14 @@ pub async fn board(
     }
 
     // Restoring messages from DB
+    let viewer = viewer_hash(&req, &data.config.ip_hash_salt);
     for row in client
-        .get_messages(&info.board, current_page, data.config.page_limit as i64)
+        .get_messages(
+            &info.board,
+            current_page,
+            settings.page_limit as i64,
+            &viewer,
+        )
         .await
         .unwrap()
         .into_iter()
@@ -73,38 +86,73 @@ pub async fn board_process_form(
     form: MultipartForm<MsgForm>,
     info: web::Path<PathInfo>,
     data: web::Data<ApplicationState<'_>>,
+    req: HttpRequest,
 ) -> impl Responder {
-    const MAX_AUTHOR_LENGTH: usize = 250;
-    const MAX_MESSAGE_LENGTH: usize = 4000;
+    let settings = match data.config.board_settings(&info.board) {
+        Some(s) => s,
+        None => return web::Redirect::to("/error?error_code=404").see_other(),
+    };
 
-    if !data.config.boards.contains_key(&info.board) {
-        return web::Redirect::to("/error?error_code=404").see_other();
-    }
+    let client = &data.db_client;
 
-    let client = data.db_client.lock().await;
+    // banned posters are sent to the ban page
+    let poster = match PosterIdentity::from_request(&req, &data.config.ip_hash_salt) {
+        Some(p) => p,
+        None => return web::Redirect::to("/error?error_code=500").see_other(),
+    };
+    let shadow_banned = match check_bans(client, &info.board, &poster).await {
+        Ok(s) => s,
+        Err(redirect) => return redirect.see_other(),
+    };
 
     let trimmed_author = form.author.trim();
     let trimmed_message = form.message.trim();
 
     // message/author name length checks
-    if trimmed_author.len() > MAX_AUTHOR_LENGTH
+    if trimmed_author.len() > settings.max_author_length
         || trimmed_message.is_empty()
-        || trimmed_message.len() > MAX_MESSAGE_LENGTH
+        || trimmed_message.len() > settings.max_message_length
     {
         return web::Redirect::to("/error?error_code=403").see_other();
     }
 
-    let filtered_author = match trimmed_author.len() {
-        0 => "Anonymous".to_string(), // automatically set if no author name
-        _ => data.formatter.filter_tags(trimmed_author).await,
+    // "name#password" is split into the name and a tripcode
+    let (name, tripcode) = tripcode::split_author(trimmed_author, &data.config.tripcode_salt);
+    let name = name.trim();
+    let filtered_author = match name.len() {
+        0 => settings.default_poster_name.clone(), // automatically set if no author name
+        _ => data.formatter.filter_tags(name).await,
     };
     let filtered_msg = data.formatter.filter_tags(trimmed_message).await;
 
     // checking for banned words
-    if contains_banned_words(&filtered_author).await || contains_banned_words(&filtered_msg).await {
+    if contains_banned_words(
+        &data.banlist,
+        &info.board,
+        &filtered_author,
+        &filtered_msg,
+        &form.files,
+    ) {
         return web::Redirect::to("/error?error_code=403").see_other();
     }
 
+    // wordfilters are applied to posts that passed the banlist, so they can't hide banned words
+    let filtered_author = match name.len() {
+        0 => filtered_author,
+        _ => rewrite_words(
+            &data.wordfilters,
+            &info.board,
+            PostField::Author,
+            &filtered_author,
+        ),
+    };
+    let filtered_msg = rewrite_words(
+        &data.wordfilters,
+        &info.board,
+        PostField::Message,
+        &filtered_msg,
+    );
+
     // checking for correct captcha
     let hash_true = form.captcha_hash.to_string();
     let hash_sent = sha256::digest(form.captcha_answer.to_string());
@@ -118,7 +166,29 @@ pub async fn board_process_form(
     // Checking against the last message (to prevent spam)
     if let Ok(last_msg) = client.get_last_message(&info.board).await {
         if last_msg.msg == filtered_msg {
-            return web::Redirect::to("/error?error_code=403").see_other();
+            return Rejection::DuplicatePost.redirect().see_other();
+        }
+    }
+
+    // cooldowns, link limits, near-duplicates and flood limits
+    match antispam::check_post(
+        client,
+        &settings,
+        &info.board,
+        &poster.hash,
+        true,
+        &filtered_msg,
+    )
+    .await
+    {
+        Ok(None) => (),
+        Ok(Some(rejection)) => {
+            log::info!("Rejected post on /{}/: {:?}", info.board, rejection);
+            return rejection.redirect().see_other();
+        }
+        Err(e) => {
+            log::error!("Failed to run anti-spam checks: {:?}", e);
+            return web::Redirect::to("/error?error_code=500").see_other();
         }
     }
 
@@ -126,26 +196,63 @@ pub async fn board_process_form(
     // getting time
     let since_epoch = html_proc::since_epoch();
 
-    let filepath_collection = process_files(&form.files).await;
+    let attachments = process_files(
+        &form.files,
+        settings.max_attachments,
+        &settings.allowed_media_types,
+    )
+    .await;
 
-    client
-        .insert_to_messages(
-            &info.board,
-            since_epoch,
-            &filtered_author,
-            &filtered_msg,
-            &filepath_collection,
-            since_epoch,
-        )
-        .await;
+    // some boards don't accept new threads without media
+    if settings.require_image && attachments.is_empty() {
+        return web::Redirect::to("/error?error_code=403").see_other();
+    }
 
-    // after sending, get number of messages on the board
-    let msg_count = client.count_messages(&info.board).await.unwrap();
+    let visibility =
+        match post_visibility(client, &settings, &info.board, &poster, true, shadow_banned).await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Failed to check moderation policy: {:?}", e);
+                db_control::discard_new_attachments(&attachments);
+                return web::Redirect::to("/error?error_code=500").see_other();
+            }
+        };
 
-    // delete a message if total message number is over the hard limit
-    if msg_count > data.config.hard_limit.into() {
-        client.delete_least_active(&info.board).await;
+    let new_post = db_control::NewPost {
+        board: &info.board,
+        time: since_epoch,
+        author: &filtered_author,
+        tripcode: &tripcode,
+        body: &filtered_msg,
+        poster_hash: &poster.hash,
+        attachments: &attachments,
+        visibility,
+    };
+
+    // least active messages are archived or deleted if total message number is over the hard limit
+    let board_settings = &data.config.boards[&info.board];
+    let archive = board_settings.limit_policy == crate::LimitPolicy::Archive;
+    if let Err(e) = client
+        .create_message(&new_post, settings.hard_limit.into(), archive)
+        .await
+    {
+        log::error!("Failed to create message: {:?}", e);
+        db_control::discard_new_attachments(&attachments);
+        return web::Redirect::to("/error?error_code=500").see_other();
     }
 
+    // keeping the archive within its size limit
+    if archive && board_settings.archive_limit > 0 {
+        if let Err(e) = client
+            .prune_archive(&info.board, 0, board_settings.archive_limit.into())
+            .await
+        {
+            log::error!("Failed to prune archive of /{}/: {:?}", info.board, e);
+        }
+    }
+
+    if visibility == db_control::PostVisibility::Held {
+        return web::Redirect::to(format!("/awaiting_approval?board={}", info.board)).see_other();
+    }
     web::Redirect::to(format!("/{}", info.board)).see_other()
 }
@@ -1,6 +1,6 @@
 //! Handler for board post catalogs
 
-use actix_web::{get, web, HttpResponse, Responder};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
 
 use crate::html_proc;
 use crate::routes::ApplicationState;
@@ -13,11 +13,14 @@ pub async fn board_catalog(
     data: web::Data<ApplicationState<'_>>,
     page_data: web::Query<QueryOptions>,
     info: web::Path<PathInfo>,
+    req: HttpRequest,
 ) -> impl Responder {
-    if !data.config.boards.contains_key(&info.board) {
-        return HttpResponse::Ok().body("Does not exist");
-    }
-    let client = data.db_client.lock().await;
+    let page_limit = match data.config.board_settings(&info.board) {
+        Some(s) => s.page_limit as i64,
+        None => return HttpResponse::Ok().body("Does not exist"),
+    };
+    let client = &data.db_client;
+    let viewer = crate::routes::viewer_hash(&req, &data.config.ip_hash_salt);
     let mut inserted_msg = String::from("");
 
     let mut current_page = page_data.page.unwrap_or(1);
@@ -25,37 +28,47 @@ pub async fn board_catalog(
         current_page = 1;
     }
 
-    let catalog_messages;
+    let mut search_summary = String::new();
     if let Some(search_string) = &page_data.search_string {
-        catalog_messages = client
-            .search_messages(
+        let result_count = client
+            .count_search_results(&info.board, search_string, &viewer)
+            .await
+            .unwrap_or(0);
+        search_summary = format!("Found {} posts", result_count);
+
+        let search_results = client
+            .search_posts(
                 &info.board,
-                (current_page - 1) * data.config.page_limit as i64,
-                data.config.page_limit as i64,
                 search_string,
+                current_page,
+                page_limit,
+                &viewer,
             )
             .await
-            .unwrap();
+            .unwrap_or_default();
+        for row in search_results.into_iter() {
+            inserted_msg.push_str(data.formatter.format_into_search_result(row).await.as_str());
+        }
     } else {
-        catalog_messages = client
-            .get_messages(&info.board, current_page, data.config.page_limit as i64)
+        // Restoring messages from DB
+        for row in client
+            .get_messages(&info.board, current_page, page_limit, &viewer)
             .await
             .unwrap()
-    }
-
-    // Restoring messages from DB
-    for row in catalog_messages.into_iter() {
-        inserted_msg.push_str(
-            data.formatter
-                .format_into_message(
-                    html_proc::BoardMessageType::CatalogMessage,
-                    row,
-                    &current_page.to_string(),
-                    None,
-                )
-                .await
-                .as_str(),
-        );
+            .into_iter()
+        {
+            inserted_msg.push_str(
+                data.formatter
+                    .format_into_message(
+                        html_proc::BoardMessageType::CatalogMessage,
+                        row,
+                        &current_page.to_string(),
+                        None,
+                    )
+                    .await
+                    .as_str(),
+            );
+        }
     }
 
     let link_queries = page_data.into_inner().get_neighbour_pages();
@@ -65,6 +78,7 @@ pub async fn board_catalog(
             .format_into_catalog(
                 &info.board,
                 &inserted_msg,
+                &search_summary,
                 &link_queries.0.to_string(),
                 &link_queries.1.to_string(),
             )
@@ -1,27 +1,384 @@
 //! Handlers for admin dashboard and login page
 
-use crate::routes::ApplicationState;
-use actix_web::{get, post, web, HttpResponse, Responder};
+use crate::banlist::BanlistEntry;
+use crate::db_control::audit::{self, AuditAction, AuditFilter, NewAuditEntry};
+use crate::db_control::bans::BanTarget;
+use crate::db_control::reports::ReportRow;
+use crate::db_control::staff::{self, Permission, Role, StaffRow};
+use crate::db_control::PosterSelection;
+use crate::routes::{ApplicationState, Rejection};
+use crate::rule_file::{self, PostField};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use ipnet::IpNet;
+use std::net::IpAddr;
 
-/// Query params for dashboard page switching
+/// Maximum length of public post notices, in characters
+const MAX_NOTICE_LENGTH: usize = 200;
+
+/// Maximum length of staff notes, in characters
+const MAX_NOTE_LENGTH: usize = 2000;
+
+/// Query params for dashboard page switching.
+/// `address`, `board` and `reason` prefill the ban form;
+/// `actor`, `action`, `board` and `msgid` filter the audit log;
+/// `poster`, `scope`, `board`, `msgid` and `hours` select posts for cleanup;
+/// `msgid` and `submsgid` select the post whose notes are shown
 #[derive(serde::Deserialize)]
 struct DashboardQueryOptions {
     flagged_type: Option<String>,
+    address: Option<String>,
+    board: Option<String>,
+    reason: Option<String>,
+    actor: Option<String>,
+    action: Option<String>,
+    msgid: Option<String>,
+    test_pattern: Option<String>,
+    test_text: Option<String>,
+    poster: Option<String>,
+    scope: Option<String>,
+    hours: Option<String>,
+    submsgid: Option<String>,
+}
+
+/// Form used to delete, restore or dismiss reports on posts
+#[derive(serde::Deserialize)]
+struct DeletionForm {
+    msgid: i64,
+    submsgid: Option<i64>,
+    csrf_token: String,
+}
+
+/// Form used to change thread states.
+/// `action` is one of "sticky", "unsticky", "lock" and "unlock"
+#[derive(serde::Deserialize)]
+struct ThreadStateForm {
+    msgid: i64,
+    action: String,
+    csrf_token: String,
+}
+
+/// Container for the form moving a thread to another board.
+/// A notice pointing to the new location is left behind if `notice` is checked
+#[derive(serde::Deserialize)]
+struct MoveThreadForm {
+    msgid: i64,
+    board: String,
+    notice: Option<String>,
+    csrf_token: String,
+}
+
+/// Form used to approve or reject held posts. `action` is either "approve" or "reject"
+#[derive(serde::Deserialize)]
+struct ApprovalForm {
+    msgid: i64,
+    submsgid: Option<i64>,
+    action: String,
+    csrf_token: String,
 }
 
-/// Container for query parameters regarding deleted messages
+/// Form used to set the public notice of a post. `preset` is either one of preset notices,
+/// "custom" to use `custom_notice` or empty to remove the notice
 #[derive(serde::Deserialize)]
-struct DeletionQueryOptions {
+struct NoticeForm {
     msgid: i64,
     submsgid: Option<i64>,
+    preset: String,
+    custom_notice: String,
+    csrf_token: String,
 }
 
-/// Form used to send admin login credentials
+/// Form used to add private staff notes to posts
+#[derive(serde::Deserialize)]
+struct NoteForm {
+    msgid: i64,
+    submsgid: Option<i64>,
+    note: String,
+    csrf_token: String,
+}
+
+/// Container for the form confirming deletion of posts by a poster.
+/// `scope` is one of "thread", "board" and "site"; `hours` equal to 0 means all time
+#[derive(serde::Deserialize)]
+struct CleanupForm {
+    poster: String,
+    scope: String,
+    board: String,
+    msgid: String,
+    hours: i64,
+    csrf_token: String,
+}
+
+/// Form used to lift bans
+#[derive(serde::Deserialize)]
+struct UnbanForm {
+    ban_id: i64,
+    csrf_token: String,
+}
+
+/// Form used to ban an IP address, CIDR range or poster hash.
+/// Posters are shadow-banned if `shadow` is checked
+#[derive(serde::Deserialize)]
+struct BanForm {
+    address: String,
+    board: String,
+    reason: String,
+    duration_days: u32,
+    shadow: Option<String>,
+    csrf_token: String,
+}
+
+/// Form used to add banlist entries. `boards` is a comma-separated list (empty for all boards)
+#[derive(serde::Deserialize)]
+struct BanlistForm {
+    pattern: String,
+    scope_author: Option<String>,
+    scope_message: Option<String>,
+    scope_filename: Option<String>,
+    boards: String,
+    csrf_token: String,
+}
+
+/// Form used to remove banlist entries
+#[derive(serde::Deserialize)]
+struct BanlistRemovalForm {
+    index: usize,
+    csrf_token: String,
+}
+
+/// Form used to remove staff accounts
+#[derive(serde::Deserialize)]
+struct StaffRemovalForm {
+    staff_id: i64,
+    csrf_token: String,
+}
+
+/// Form used to create staff accounts. `boards` is a comma-separated list used for janitors
+#[derive(serde::Deserialize)]
+struct StaffForm {
+    username: String,
+    password: String,
+    role: String,
+    boards: String,
+    csrf_token: String,
+}
+
+/// Form carrying nothing but the CSRF token, used by actions without parameters
+#[derive(serde::Deserialize)]
+struct CsrfForm {
+    csrf_token: String,
+}
+
+/// Form used to send staff login credentials
 #[derive(serde::Deserialize)]
 struct LoginForm {
+    username: String,
     password: String,
 }
 
+/// Returns the staff account logged in with this session, if there is one
+async fn current_staff(
+    data: &ApplicationState<'_>,
+    session: &actix_session::Session,
+) -> Option<StaffRow> {
+    let staff_id = session.get::<i64>("staff_id").ok()??;
+    data.db_client.get_staff(staff_id).await.ok()
+}
+
+/// Returns the CSRF token of this session, creating one for sessions that don't have it yet
+fn csrf_token(session: &actix_session::Session) -> String {
+    if let Ok(Some(token)) = session.get::<String>("csrf_token") {
+        return token;
+    }
+    let token = new_csrf_token();
+    if let Err(e) = session.insert("csrf_token", &token) {
+        log::error!("Failed to store CSRF token: {}", e);
+    }
+    token
+}
+
+/// Generates a random CSRF token
+fn new_csrf_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Checks a CSRF token sent along with a dashboard action against the one of this session
+fn verify_csrf_token(session: &actix_session::Session, token: &str) -> bool {
+    match session.get::<String>("csrf_token") {
+        Ok(Some(expected)) => !token.is_empty() && expected == token,
+        _ => false,
+    }
+}
+
+/// Returns the staff account acting in this session if the action carries a valid CSRF token
+/// and the account has `permission` (on `board`, if given). Otherwise, returns a redirect
+/// to the error page
+async fn authorize(
+    data: &ApplicationState<'_>,
+    session: &actix_session::Session,
+    csrf_token: &str,
+    permission: Permission,
+    board: Option<&str>,
+) -> Result<StaffRow, web::Redirect> {
+    let staff = authenticate(data, session, csrf_token).await?;
+    permit(staff, permission, board)
+}
+
+/// Same as `authorize`, for actions on a post: its board is only looked up once the session
+/// is checked, so that visitors can't learn which posts exist. Returns the account and the board
+async fn authorize_on_post(
+    data: &ApplicationState<'_>,
+    session: &actix_session::Session,
+    csrf_token: &str,
+    permission: Permission,
+    msgid: i64,
+) -> Result<(StaffRow, String), web::Redirect> {
+    let staff = authenticate(data, session, csrf_token).await?;
+    let board = target_board(data, msgid).await?;
+    Ok((permit(staff, permission, Some(&board))?, board))
+}
+
+/// Returns the staff account acting in this session if the action carries a valid CSRF token
+async fn authenticate(
+    data: &ApplicationState<'_>,
+    session: &actix_session::Session,
+    csrf_token: &str,
+) -> Result<StaffRow, web::Redirect> {
+    if !verify_csrf_token(session, csrf_token) {
+        log::warn!("Refused dashboard action with an invalid CSRF token");
+        return Err(web::Redirect::to("/error?error_code=403"));
+    }
+    current_staff(data, session)
+        .await
+        .ok_or_else(|| web::Redirect::to("/error?error_code=403"))
+}
+
+/// Passes the account through if it has `permission` (on `board`, if given)
+fn permit(
+    staff: StaffRow,
+    permission: Permission,
+    board: Option<&str>,
+) -> Result<StaffRow, web::Redirect> {
+    if staff.can(permission, board) {
+        return Ok(staff);
+    }
+    log::info!(
+        "{} was denied {:?} permission on {:?}",
+        staff.username,
+        permission,
+        board
+    );
+    Err(web::Redirect::to("/error?error_code=403"))
+}
+
+/// Returns the most common reason among reports on a post.
+/// Ties go to the reason that was reported first
+fn top_report_reason(reports: Vec<ReportRow>) -> String {
+    let mut reason_counts: Vec<(String, usize)> = Vec::new();
+    for report in reports {
+        match reason_counts.iter_mut().find(|(r, _)| *r == report.reason) {
+            Some((_, count)) => *count += 1,
+            None => reason_counts.push((report.reason, 1)),
+        }
+    }
+    reason_counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(r, _)| r)
+        .unwrap_or_default()
+}
+
+/// Runs the banlist regex tester. Without a pattern, `text` is checked against the whole banlist.
+/// Returns a description of what matched, or `None` if there is nothing to test
+fn test_banlist_pattern(data: &ApplicationState<'_>, pattern: &str, text: &str) -> Option<String> {
+    if text.is_empty() {
+        return None;
+    }
+    if pattern.is_empty() {
+        let current = data.banlist.get();
+        let matching: Vec<&str> = current
+            .entries
+            .iter()
+            .filter(|e| rule_file::compile_pattern(&e.pattern).is_ok_and(|r| r.is_match(text)))
+            .map(|e| e.pattern.as_str())
+            .collect();
+        return Some(match matching.is_empty() {
+            true => "No banlist entries match".to_string(),
+            false => format!("Matching entries: {}", matching.join(", ")),
+        });
+    }
+    Some(match rule_file::compile_pattern(pattern) {
+        Ok(r) => {
+            let found: Vec<&str> = r.find_iter(text).map(|m| m.as_str()).collect();
+            match found.is_empty() {
+                true => "No matches".to_string(),
+                false => format!("Matches: {}", found.join(", ")),
+            }
+        }
+        Err(e) => format!("Invalid pattern: {}", e),
+    })
+}
+
+/// Returns board of the post affected by a moderator action, or a redirect to the error page
+async fn target_board(data: &ApplicationState<'_>, msgid: i64) -> Result<String, web::Redirect> {
+    data.db_client
+        .get_thread_board(msgid)
+        .await
+        .map_err(|_| web::Redirect::to("/error?error_code=404"))
+}
+
+/// Narrows a poster cleanup down to its scope. Returns an explanation if the selection
+/// is invalid or the account isn't allowed to delete posts within it
+async fn resolve_cleanup(
+    data: &ApplicationState<'_>,
+    staff: &StaffRow,
+    scope: &str,
+    selection: &mut PosterSelection,
+) -> Result<(), String> {
+    if selection.poster_hash.len() != 64
+        || !selection.poster_hash.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err("Poster hash must consist of 64 hexadecimal digits".to_string());
+    }
+    match scope {
+        "thread" => {
+            selection.board = data
+                .db_client
+                .get_thread_board(selection.msgid)
+                .await
+                .map_err(|_| format!("There is no thread {}", selection.msgid))?;
+        }
+        "board" if data.config.boards.contains_key(&selection.board) => selection.msgid = 0,
+        "board" => return Err(format!("There is no board /{}/", selection.board)),
+        "site" => {
+            selection.board = String::new();
+            selection.msgid = 0;
+        }
+        _ => return Err("Unknown cleanup scope".to_string()),
+    }
+    let allowed = match selection.board.is_empty() {
+        true => data
+            .config
+            .boards
+            .keys()
+            .all(|b| staff.can(Permission::Delete, Some(b))),
+        false => staff.can(Permission::Delete, Some(&selection.board)),
+    };
+    match allowed {
+        true => Ok(()),
+        false => Err("You can't delete posts within this scope".to_string()),
+    }
+}
+
+/// Returns the earliest time of posts selected by a cleanup window
+fn cleanup_since(hours: i64) -> i64 {
+    match hours {
+        0 => 0,
+        h => crate::html_proc::since_epoch() - h * 3600,
+    }
+}
+
 /// Handler for admin dashboard
 #[get("/dashboard")]
 pub async fn view_dashboard(
@@ -29,25 +386,45 @@ pub async fn view_dashboard(
     session: actix_session::Session,
     query: web::Query<DashboardQueryOptions>,
 ) -> impl Responder {
-    let logged_in = match session.get::<bool>("logged_in") {
-        Ok(opt) => opt.unwrap_or(false),
-        Err(_) => false,
+    let staff = match current_staff(&data, &session).await {
+        Some(s) => s,
+        None => return HttpResponse::Ok().body(data.formatter.format_into_login().await),
     };
 
-    if !logged_in {
-        return HttpResponse::Ok().body(data.formatter.format_into_login().await);
-    }
-
-    let client = data.db_client.lock().await;
+    let token = csrf_token(&session);
+    let client = &data.db_client;
+    let forbidden = || {
+        HttpResponse::SeeOther()
+            .insert_header(("Location", "/error?error_code=403"))
+            .finish()
+    };
+    let not_found = || {
+        HttpResponse::SeeOther()
+            .insert_header(("Location", "/error?error_code=404"))
+            .finish()
+    };
 
     let flagged_msg_block: String = match &query.flagged_type {
         Some(n) => match n.as_str() {
             "msg" => {
+                if !staff.can(Permission::Delete, None) {
+                    return forbidden();
+                }
                 let msg_vec = client.get_flagged_messages().await;
+                let mut reports = client.get_reports().await.unwrap_or_default();
+                let mut notes = client.get_staff_notes().await.unwrap_or_default();
                 let mut result = "".to_string();
                 if let Ok(v) = msg_vec {
-                    for i in v {
+                    for mut i in v {
+                        if !staff.can(Permission::Delete, Some(&i.board)) {
+                            continue;
+                        }
                         let msgid = i.msgid;
+                        i.staff_notes = notes.remove(&(msgid, 0)).unwrap_or_default();
+                        let can_ban = staff.can(Permission::Ban, Some(&i.board));
+                        let actions =
+                            report_actions(msgid, None, &i.poster_hash, &i.board, can_ban, &token);
+                        let post_reports = reports.remove(&(msgid, 0)).unwrap_or_default();
                         result.push_str(
                             &data
                                 .formatter
@@ -60,28 +437,387 @@ pub async fn view_dashboard(
                                 .await,
                         );
                         result.push_str(
-                            format!("<a href=\"/delete?msgid={}\">Delete</a>\n", msgid).as_str(),
+                            &data
+                                .formatter
+                                .format_into_report_summary(&post_reports)
+                                .await,
                         );
+                        result.push_str(&actions);
                         result.push('\n');
                     }
                 }
                 result
             }
+            "queue" => {
+                if !staff.can(Permission::Delete, None) {
+                    return forbidden();
+                }
+                let mut result = "<h2>Held threads</h2>\n".to_string();
+                if let Ok(v) = client.get_held_messages().await {
+                    for i in v {
+                        if !staff.can(Permission::Delete, Some(&i.board)) {
+                            continue;
+                        }
+                        let actions =
+                            approval_actions(i.msgid, None, &i.poster_hash, &i.board, &token);
+                        result.push_str(
+                            &data
+                                .formatter
+                                .format_into_message(
+                                    crate::html_proc::BoardMessageType::Message,
+                                    i,
+                                    "1",
+                                    None,
+                                )
+                                .await,
+                        );
+                        result.push_str(&actions);
+                    }
+                }
+                result.push_str("<h2>Held posts</h2>\n");
+                if let Ok(v) = client.get_held_submessages().await {
+                    for i in v {
+                        if !staff.can(Permission::Delete, Some(&i.board)) {
+                            continue;
+                        }
+                        let actions = approval_actions(
+                            i.parent_msg,
+                            Some(i.submsg_id),
+                            &i.poster_hash,
+                            &i.board,
+                            &token,
+                        );
+                        result.push_str(&data.formatter.format_into_submessage(i).await);
+                        result.push_str(&actions);
+                    }
+                }
+                result
+            }
+            "trash" => {
+                if !staff.can(Permission::Delete, None) {
+                    return forbidden();
+                }
+                let mut result = "<h2>Deleted threads</h2>\n".to_string();
+                if let Ok(v) = client.get_deleted_messages().await {
+                    for i in v {
+                        if !staff.can(Permission::Delete, Some(&i.board)) {
+                            continue;
+                        }
+                        let msgid = i.msgid;
+                        let deleted_at = i.deleted_at.unwrap_or(0);
+                        result.push_str(
+                            &data
+                                .formatter
+                                .format_into_message(
+                                    crate::html_proc::BoardMessageType::Message,
+                                    i,
+                                    "1",
+                                    None,
+                                )
+                                .await,
+                        );
+                        result.push_str(&format!(
+                            "Deleted {} | {}\n",
+                            crate::html_proc::get_time(deleted_at),
+                            action_button("/restore", &post_fields(msgid, None), "Restore", &token)
+                        ));
+                        result.push('\n');
+                    }
+                }
+                result.push_str("<h2>Deleted posts</h2>\n");
+                if let Ok(v) = client.get_deleted_submessages().await {
+                    for i in v {
+                        if !staff.can(Permission::Delete, Some(&i.board)) {
+                            continue;
+                        }
+                        let parent_msg = i.parent_msg;
+                        let submsg_id = i.submsg_id;
+                        let deleted_at = i.deleted_at.unwrap_or(0);
+                        result.push_str(&data.formatter.format_into_submessage(i).await);
+                        result.push_str(&format!(
+                            "Deleted {} | {}\n",
+                            crate::html_proc::get_time(deleted_at),
+                            action_button(
+                                "/restore",
+                                &post_fields(parent_msg, Some(submsg_id)),
+                                "Restore",
+                                &token
+                            )
+                        ));
+                        result.push('\n');
+                    }
+                }
+                result
+            }
+            "threads" => {
+                if !staff.can(Permission::Delete, None) {
+                    return forbidden();
+                }
+                let boards: Vec<&String> = data
+                    .config
+                    .boards
+                    .keys()
+                    .filter(|b| staff.can(Permission::Delete, Some(b)))
+                    .collect();
+                let mut result = data
+                    .formatter
+                    .format_into_thread_tools(boards, &token)
+                    .await;
+                result.push_str("<h2>Sticky and locked threads</h2>\n");
+                if let Ok(v) = client.get_moderated_threads().await {
+                    for i in v {
+                        if !staff.can(Permission::Delete, Some(&i.board)) {
+                            continue;
+                        }
+                        let msgid = i.msgid;
+                        let sticky_action = match i.stickied_at {
+                            Some(_) => ("unsticky", "Unsticky"),
+                            None => ("sticky", "Make sticky"),
+                        };
+                        let lock_action = match i.locked_at {
+                            Some(_) => ("unlock", "Unlock"),
+                            None => ("lock", "Lock"),
+                        };
+                        result.push_str(
+                            &data
+                                .formatter
+                                .format_into_message(
+                                    crate::html_proc::BoardMessageType::Message,
+                                    i,
+                                    "1",
+                                    None,
+                                )
+                                .await,
+                        );
+                        let buttons: Vec<String> = [sticky_action, lock_action]
+                            .iter()
+                            .map(|(action, label)| {
+                                let fields =
+                                    [("msgid", msgid.to_string()), ("action", action.to_string())];
+                                action_button("/thread_state", &fields, label, &token)
+                            })
+                            .collect();
+                        result.push_str(&format!(
+                            "<div class=\"post_actions\">{}</div>\n",
+                            buttons.join(" | ")
+                        ));
+                    }
+                }
+                result
+            }
+            "bans" => {
+                if !staff.can(Permission::Ban, None) {
+                    return forbidden();
+                }
+                let bans = client.get_active_bans().await.unwrap_or_else(|e| {
+                    log::error!("Failed to get bans: {:?}", e);
+                    Vec::new()
+                });
+                data.formatter
+                    .format_into_ban_list(
+                        bans,
+                        data.config.boards.keys().collect(),
+                        query.address.as_deref().unwrap_or(""),
+                        query.board.as_deref().unwrap_or(""),
+                        query.reason.as_deref().unwrap_or(""),
+                        &token,
+                    )
+                    .await
+            }
+            "staff" => {
+                if !staff.can(Permission::ManageStaff, None) {
+                    return forbidden();
+                }
+                let accounts = client.get_all_staff().await.unwrap_or_else(|e| {
+                    log::error!("Failed to get staff accounts: {:?}", e);
+                    Vec::new()
+                });
+                data.formatter
+                    .format_into_staff_list(accounts, &token)
+                    .await
+            }
+            "cleanup" => {
+                if !staff.can(Permission::Delete, None) {
+                    return forbidden();
+                }
+                let scope = query.scope.as_deref().unwrap_or("board");
+                let hours = query
+                    .hours
+                    .as_deref()
+                    .and_then(|h| h.parse().ok())
+                    .unwrap_or(24);
+                let mut selection = PosterSelection {
+                    poster_hash: query.poster.as_deref().unwrap_or("").trim().to_lowercase(),
+                    board: query.board.clone().unwrap_or_default(),
+                    msgid: query
+                        .msgid
+                        .as_deref()
+                        .and_then(|m| m.trim().parse().ok())
+                        .unwrap_or(0),
+                    since: cleanup_since(hours),
+                };
+                let preview = match selection.poster_hash.is_empty() {
+                    true => None,
+                    false => Some(
+                        match resolve_cleanup(&data, &staff, scope, &mut selection).await {
+                            Ok(()) => client.count_poster_posts(&selection).await.map_err(|e| {
+                                log::error!("Failed to count posts by poster: {:?}", e);
+                                "Failed to count matching posts".to_string()
+                            }),
+                            Err(e) => Err(e),
+                        },
+                    ),
+                };
+                let boards: Vec<&String> = data
+                    .config
+                    .boards
+                    .keys()
+                    .filter(|b| staff.can(Permission::Delete, Some(b)))
+                    .collect();
+                data.formatter
+                    .format_into_cleanup(boards, scope, &selection, hours, preview, &token)
+                    .await
+            }
+            "notes" => {
+                let msgid = query
+                    .msgid
+                    .as_deref()
+                    .and_then(|m| m.trim().parse().ok())
+                    .unwrap_or(0);
+                let submsgid: Option<i64> = query
+                    .submsgid
+                    .as_deref()
+                    .and_then(|m| m.trim().parse().ok())
+                    .filter(|&m| m != 0);
+                let submsg_id = submsgid.unwrap_or(0);
+                let notes = client
+                    .get_post_staff_notes(msgid, submsg_id)
+                    .await
+                    .unwrap_or_else(|e| {
+                        log::error!("Failed to get staff notes: {:?}", e);
+                        Vec::new()
+                    });
+                let (board, mod_notice, post) = match submsgid {
+                    Some(submsgid) => match client.get_single_submessage(msgid, submsgid).await {
+                        Ok(mut m) => {
+                            m.staff_notes = notes;
+                            (
+                                m.board.clone(),
+                                m.mod_notice.clone(),
+                                data.formatter.format_into_submessage(m).await,
+                            )
+                        }
+                        Err(_) => return not_found(),
+                    },
+                    None => match client.get_single_message(msgid).await {
+                        Ok(mut m) => {
+                            m.staff_notes = notes;
+                            (
+                                m.board.clone(),
+                                m.mod_notice.clone(),
+                                data.formatter
+                                    .format_into_message(
+                                        crate::html_proc::BoardMessageType::Message,
+                                        m,
+                                        "1",
+                                        None,
+                                    )
+                                    .await,
+                            )
+                        }
+                        Err(_) => return not_found(),
+                    },
+                };
+                if !staff.can(Permission::Delete, Some(&board)) {
+                    return forbidden();
+                }
+                let mut result = post;
+                result.push_str(
+                    &data
+                        .formatter
+                        .format_into_post_notes(msgid, submsgid, &mod_notice, &token)
+                        .await,
+                );
+                result
+            }
+            "banlist" => {
+                if !staff.can(Permission::ManageBoards, None) {
+                    return forbidden();
+                }
+                let test_text = query.test_text.as_deref().unwrap_or("");
+                let test_pattern = query.test_pattern.as_deref().unwrap_or("");
+                let test_result = test_banlist_pattern(&data, test_pattern, test_text);
+                data.formatter
+                    .format_into_banlist(
+                        &data.banlist.get().entries,
+                        data.config.boards.keys().collect(),
+                        test_pattern,
+                        test_text,
+                        test_result.as_deref(),
+                        &token,
+                    )
+                    .await
+            }
+            "audit" => {
+                if !staff.can(Permission::ManageStaff, None) {
+                    return forbidden();
+                }
+                let filter = AuditFilter {
+                    actor: query.actor.as_deref().unwrap_or("").trim(),
+                    action: query.action.as_deref().unwrap_or(""),
+                    board: query.board.as_deref().unwrap_or(""),
+                    msgid: query
+                        .msgid
+                        .as_deref()
+                        .and_then(|m| m.trim().parse().ok())
+                        .unwrap_or(0),
+                };
+                let entries = client
+                    .get_audit_log(&filter, audit::AUDIT_PAGE_SIZE)
+                    .await
+                    .unwrap_or_else(|e| {
+                        log::error!("Failed to get audit log: {:?}", e);
+                        Vec::new()
+                    });
+                data.formatter
+                    .format_into_audit_log(entries, data.config.boards.keys().collect(), &filter)
+                    .await
+            }
             _ => {
+                if !staff.can(Permission::Delete, None) {
+                    return forbidden();
+                }
                 let msg_vec = client.get_flagged_submessages().await;
+                let mut reports = client.get_reports().await.unwrap_or_default();
+                let mut notes = client.get_staff_notes().await.unwrap_or_default();
                 let mut result = "".to_string();
                 if let Ok(v) = msg_vec {
-                    for i in v {
+                    for mut i in v {
+                        if !staff.can(Permission::Delete, Some(&i.board)) {
+                            continue;
+                        }
                         let parent_msg = i.parent_msg;
                         let submsg_id = i.submsg_id;
+                        i.staff_notes = notes.remove(&(parent_msg, submsg_id)).unwrap_or_default();
+                        let can_ban = staff.can(Permission::Ban, Some(&i.board));
+                        let actions = report_actions(
+                            parent_msg,
+                            Some(submsg_id),
+                            &i.poster_hash,
+                            &i.board,
+                            can_ban,
+                            &token,
+                        );
+                        let post_reports =
+                            reports.remove(&(parent_msg, submsg_id)).unwrap_or_default();
                         result.push_str(&data.formatter.format_into_submessage(i).await);
                         result.push_str(
-                            format!(
-                                "<a href=\"/delete?msgid={}&submsgid={}\">Delete</a>\n",
-                                parent_msg, submsg_id
-                            )
-                            .as_str(),
+                            &data
+                                .formatter
+                                .format_into_report_summary(&post_reports)
+                                .await,
                         );
+                        result.push_str(&actions);
                         result.push('\n');
                     }
                 }
@@ -108,51 +844,1096 @@ pub async fn view_dashboard(
 
     HttpResponse::Ok().body(
         data.formatter
-            .format_into_dashboard(flagged_msg_block)
+            .format_into_dashboard(flagged_msg_block, &staff, &token)
             .await,
     )
 }
 
+/// Inline form submitting a dashboard action, with `fields` and the CSRF token as hidden inputs
+fn action_button(action: &str, fields: &[(&str, String)], label: &str, csrf_token: &str) -> String {
+    let inputs: String = fields
+        .iter()
+        .chain([("csrf_token", csrf_token.to_string())].iter())
+        .map(|(name, value)| {
+            format!(
+                "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                name, value
+            )
+        })
+        .collect();
+    format!(
+        "<form class=\"action_form\" action=\"{}\" method=\"post\">{}<input type=\"submit\" value=\"{}\"></form>",
+        action, inputs, label
+    )
+}
+
+/// Form fields identifying a message (thread) or submessage
+fn post_fields(msgid: i64, submsgid: Option<i64>) -> Vec<(&'static str, String)> {
+    let mut fields = vec![("msgid", msgid.to_string())];
+    if let Some(submsgid) = submsgid {
+        fields.push(("submsgid", submsgid.to_string()));
+    }
+    fields
+}
+
+/// Actions for a post held for approval
+fn approval_actions(
+    msgid: i64,
+    submsgid: Option<i64>,
+    poster_hash: &str,
+    board: &str,
+    csrf_token: &str,
+) -> String {
+    let mut buttons: Vec<String> = [("approve", "Approve"), ("reject", "Reject")]
+        .iter()
+        .map(|(action, label)| {
+            let mut fields = post_fields(msgid, submsgid);
+            fields.push(("action", action.to_string()));
+            action_button("/approve", &fields, label, csrf_token)
+        })
+        .collect();
+    if !poster_hash.is_empty() {
+        buttons.push(format!(
+            "<a href=\"/dashboard?flagged_type=bans&address={}&board={}\">Ban</a>",
+            poster_hash, board
+        ));
+    }
+    format!(
+        "<div class=\"post_actions\">{}</div>\n",
+        buttons.join(" | ")
+    )
+}
+
+/// Actions for a reported post. Posters can only be banned if their identity is known
+fn report_actions(
+    msgid: i64,
+    submsgid: Option<i64>,
+    poster_hash: &str,
+    board: &str,
+    can_ban: bool,
+    csrf_token: &str,
+) -> String {
+    let fields = post_fields(msgid, submsgid);
+    let mut actions = format!(
+        "<div class=\"post_actions\">{} | {}",
+        action_button("/dismiss", &fields, "Dismiss", csrf_token),
+        action_button("/delete", &fields, "Delete", csrf_token)
+    );
+    actions.push_str(&format!(
+        " | <a href=\"/dashboard?flagged_type=notes&msgid={}&submsgid={}\">Notice and notes</a>",
+        msgid,
+        submsgid.unwrap_or(0)
+    ));
+    if !poster_hash.is_empty() {
+        actions.push_str(&format!(
+            " | <a href=\"/dashboard?flagged_type=cleanup&poster={}&scope=thread&msgid={}&board={}\">Delete all by poster</a>",
+            poster_hash, msgid, board
+        ));
+    }
+    if can_ban && !poster_hash.is_empty() {
+        actions.push_str(&format!(
+            " | <a href=\"/dashboard?flagged_type=bans&address={}&board={}\">Ban</a> | {}",
+            poster_hash,
+            board,
+            action_button("/delete_and_ban", &fields, "Delete and ban", csrf_token)
+        ));
+    }
+    actions.push_str("</div>\n");
+    actions
+}
+
 /// Handler for processing login credentials
 #[post("/dashboard")]
 pub async fn login_page(
+    req: HttpRequest,
     data: web::Data<ApplicationState<'_>>,
     session: actix_session::Session,
     form: web::Form<LoginForm>,
 ) -> impl Responder {
+    let client_ip = req.peer_addr().map(|a| a.ip().to_canonical());
+    if let Some(ip) = &client_ip {
+        if let Some(left) = data.login_throttle.locked_for(ip) {
+            log::warn!(
+                "Refused login attempt for {} from locked out client {} ({}s left)",
+                form.username.trim(),
+                ip,
+                left
+            );
+            return Rejection::LoginLocked.redirect().see_other();
+        }
+    }
+
     // the password is stored as a hash. no im not hashing it client-side.
-    if sha256::digest(&form.password) == data.config.admin_password {
-        let session_insert_result = session.insert("logged_in", true);
-        match session_insert_result {
-            Ok(_) => log::info!("Admin successfully authorized"),
-            Err(_) => log::error!("Failed to authorize admin"),
+    // hashing is slow on purpose, so it's kept off the async workers
+    let account = data.db_client.get_staff_by_name(form.username.trim()).await;
+    let password_hash = match &account {
+        Ok(a) => a.password_hash.clone(),
+        Err(_) => staff::DUMMY_PASSWORD_HASH.to_string(),
+    };
+    let password = form.password.clone();
+    let verified = web::block(move || staff::verify_password(&password, &password_hash))
+        .await
+        .unwrap_or(false);
+    let account = match account {
+        Ok(a) if verified => a,
+        _ => {
+            log::info!(
+                "Failed login attempt for {} from {}",
+                form.username.trim(),
+                client_ip.map_or("unknown address".to_string(), |ip| ip.to_string())
+            );
+            if let Some(ip) = &client_ip {
+                if data.login_throttle.record_failure(ip) {
+                    log::warn!("Client {} is locked out after too many failed logins", ip);
+                }
+            }
+            return web::Redirect::to("/error?error_code=403").see_other();
         }
-        web::Redirect::to("/dashboard").see_other()
-    } else {
-        web::Redirect::to("/error?error_code=403").see_other()
+    };
+    if let Some(ip) = &client_ip {
+        data.login_throttle.clear(ip);
     }
+
+    // hashes from earlier versions are replaced as soon as the password is known
+    if staff::is_legacy_hash(&account.password_hash) {
+        let password = form.password.clone();
+        if let Ok(new_hash) = web::block(move || staff::hash_password(&password)).await {
+            match data
+                .db_client
+                .set_password_hash(account.staff_id, &new_hash)
+                .await
+            {
+                Ok(_) => log::info!("Upgraded password hash of {}", account.username),
+                Err(e) => log::error!("Failed to upgrade password hash: {:?}", e),
+            }
+        }
+    }
+    session.renew();
+    match session
+        .insert("staff_id", account.staff_id)
+        .and_then(|_| session.insert("csrf_token", new_csrf_token()))
+    {
+        Ok(_) => {
+            log::info!("{} successfully logged in", account.username);
+            data.db_client
+                .log_action(&NewAuditEntry::new(&account.username, AuditAction::Login))
+                .await;
+        }
+        Err(_) => log::error!("Failed to authorize {}", account.username),
+    }
+    web::Redirect::to("/dashboard").see_other()
+}
+
+/// Handler for logging out of the dashboard
+#[post("/logout")]
+pub async fn logout(session: actix_session::Session, form: web::Form<CsrfForm>) -> impl Responder {
+    if !verify_csrf_token(&session, &form.csrf_token) {
+        return web::Redirect::to("/error?error_code=403").see_other();
+    }
+    session.purge();
+    web::Redirect::to("/dashboard").see_other()
 }
 
 /// Handler for flagged message deletion
-#[get("/delete")]
+#[post("/delete")]
 pub async fn delete_msg(
     data: web::Data<ApplicationState<'_>>,
     session: actix_session::Session,
-    query: web::Query<DeletionQueryOptions>,
+    form: web::Form<DeletionForm>,
 ) -> impl Responder {
-    if let Ok(Some(logged_in)) = session.get::<bool>("logged_in") {
-        if !logged_in {
-            return web::Redirect::to("/error?error_code=403").see_other();
-        }
+    let (staff, _) = match authorize_on_post(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::Delete,
+        form.msgid,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(redirect) => return redirect.see_other(),
+    };
+    log::info!(
+        "{} deleted post {}/{}",
+        staff.username,
+        form.msgid,
+        form.submsgid.unwrap_or(0)
+    );
+    let client = &data.db_client;
+    let submsg_id = form.submsgid.unwrap_or(0);
+    let reason = top_report_reason(
+        client
+            .get_post_reports(form.msgid, submsg_id)
+            .await
+            .unwrap_or_default(),
+    );
+    client
+        .log_action(&NewAuditEntry {
+            reason: &reason,
+            ..NewAuditEntry::on_post(&staff.username, AuditAction::Delete, form.msgid, submsg_id)
+        })
+        .await;
+    if let Some(submsgid) = form.submsgid {
+        client.delete_submsg(form.msgid, submsgid).await;
+        web::Redirect::to("/dashboard?flagged_type=submsg").see_other()
     } else {
+        client.delete_msg(form.msgid).await;
+        web::Redirect::to("/dashboard?flagged_type=msg").see_other()
+    }
+}
+
+/// Handler for deleting every post by a poster within a thread, board or the whole site
+#[post("/delete_by_poster")]
+pub async fn delete_by_poster(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<CleanupForm>,
+) -> impl Responder {
+    let staff = match authorize(&data, &session, &form.csrf_token, Permission::Delete, None).await {
+        Ok(s) => s,
+        Err(redirect) => return redirect.see_other(),
+    };
+    let mut selection = PosterSelection {
+        poster_hash: form.poster.trim().to_lowercase(),
+        board: form.board.clone(),
+        msgid: form.msgid.trim().parse().unwrap_or(0),
+        since: cleanup_since(form.hours),
+    };
+    if let Err(e) = resolve_cleanup(&data, &staff, &form.scope, &mut selection).await {
+        log::info!("{} was refused poster cleanup: {}", staff.username, e);
         return web::Redirect::to("/error?error_code=403").see_other();
     }
-    let client = data.db_client.lock().await;
-    if let Some(submsgid) = query.submsgid {
-        client.delete_submsg(query.msgid, submsgid).await;
-        web::Redirect::to("/dashboard?flagged_type=submsg").see_other()
+    let client = &data.db_client;
+    let posts = match client.get_poster_posts(&selection).await {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!("Failed to find posts by poster: {:?}", e);
+            return web::Redirect::to("/error?error_code=500").see_other();
+        }
+    };
+    let window = crate::html_proc::CLEANUP_WINDOWS
+        .iter()
+        .find(|(h, _)| *h == form.hours)
+        .map_or_else(
+            || format!("last {} hours", form.hours),
+            |(_, t)| t.to_lowercase(),
+        );
+    let scope = match form.scope.as_str() {
+        "thread" => format!("thread {}", selection.msgid),
+        "board" => format!("/{}/", selection.board),
+        _ => "whole site".to_string(),
+    };
+    let reason = format!("{}, {}", scope, window);
+    // every post gets its own entry, so that its snapshot outlives the trash
+    for &(msgid, submsg_id) in &posts {
+        client
+            .log_action(&NewAuditEntry {
+                target: &selection.poster_hash,
+                reason: &reason,
+                ..NewAuditEntry::on_post(
+                    &staff.username,
+                    AuditAction::DeleteByPoster,
+                    msgid,
+                    submsg_id,
+                )
+            })
+            .await;
+    }
+    let (threads, replies) = match client.delete_poster_posts(&posts).await {
+        Ok(n) => n,
+        Err(e) => {
+            log::error!("Failed to delete posts by poster: {:?}", e);
+            return web::Redirect::to("/error?error_code=500").see_other();
+        }
+    };
+    log::info!(
+        "{} deleted {} threads and {} replies by {}",
+        staff.username,
+        threads,
+        replies,
+        selection.poster_hash
+    );
+    web::Redirect::to(format!(
+        "/dashboard?flagged_type=cleanup&poster={}&scope={}&board={}&msgid={}&hours={}",
+        selection.poster_hash, form.scope, selection.board, selection.msgid, form.hours
+    ))
+    .see_other()
+}
+
+/// Handler for approving held posts, or rejecting them by moving them into trash
+#[post("/approve")]
+pub async fn approve_post(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<ApprovalForm>,
+) -> impl Responder {
+    let (staff, _) = match authorize_on_post(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::Delete,
+        form.msgid,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(redirect) => return redirect.see_other(),
+    };
+    let client = &data.db_client;
+    let submsg_id = form.submsgid.unwrap_or(0);
+    let (action, verb) = match form.action.as_str() {
+        "approve" => match client.approve_post(form.msgid, submsg_id).await {
+            Ok(true) => (AuditAction::Approve, "approved"),
+            Ok(false) => return web::Redirect::to("/error?error_code=404").see_other(),
+            Err(e) => {
+                log::error!("Failed to approve post: {:?}", e);
+                return web::Redirect::to("/error?error_code=500").see_other();
+            }
+        },
+        "reject" => {
+            match form.submsgid {
+                Some(submsgid) => client.delete_submsg(form.msgid, submsgid).await,
+                None => client.delete_msg(form.msgid).await,
+            };
+            (AuditAction::Reject, "rejected")
+        }
+        _ => return web::Redirect::to("/error?error_code=400").see_other(),
+    };
+    log::info!(
+        "{} {} post {}/{}",
+        staff.username,
+        verb,
+        form.msgid,
+        submsg_id
+    );
+    client
+        .log_action(&NewAuditEntry::on_post(
+            &staff.username,
+            action,
+            form.msgid,
+            submsg_id,
+        ))
+        .await;
+    web::Redirect::to("/dashboard?flagged_type=queue").see_other()
+}
+
+/// Handler for setting or removing the public notice of a post
+#[post("/post_notice")]
+pub async fn set_post_notice(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<NoticeForm>,
+) -> impl Responder {
+    let (staff, _) = match authorize_on_post(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::Delete,
+        form.msgid,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(redirect) => return redirect.see_other(),
+    };
+    let notice = match form.preset.as_str() {
+        "custom" => form.custom_notice.trim(),
+        preset => preset,
+    };
+    if notice.chars().count() > MAX_NOTICE_LENGTH {
+        return web::Redirect::to("/error?error_code=400").see_other();
+    }
+    let submsg_id = form.submsgid.unwrap_or(0);
+    let client = &data.db_client;
+    match client.set_mod_notice(form.msgid, submsg_id, notice).await {
+        Ok(true) => (),
+        Ok(false) => return web::Redirect::to("/error?error_code=404").see_other(),
+        Err(e) => {
+            log::error!("Failed to set post notice: {:?}", e);
+            return web::Redirect::to("/error?error_code=500").see_other();
+        }
+    }
+    log::info!(
+        "{} set notice of post {}/{} to \"{}\"",
+        staff.username,
+        form.msgid,
+        submsg_id,
+        notice
+    );
+    client
+        .log_action(&NewAuditEntry {
+            reason: notice,
+            ..NewAuditEntry::on_post(
+                &staff.username,
+                AuditAction::SetNotice,
+                form.msgid,
+                submsg_id,
+            )
+        })
+        .await;
+    web::Redirect::to(format!(
+        "/dashboard?flagged_type=notes&msgid={}&submsgid={}",
+        form.msgid, submsg_id
+    ))
+    .see_other()
+}
+
+/// Handler for adding private staff notes to posts
+#[post("/post_note")]
+pub async fn add_post_note(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<NoteForm>,
+) -> impl Responder {
+    let (staff, _) = match authorize_on_post(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::Delete,
+        form.msgid,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(redirect) => return redirect.see_other(),
+    };
+    let note = form.note.trim();
+    if note.is_empty() || note.chars().count() > MAX_NOTE_LENGTH {
+        return web::Redirect::to("/error?error_code=400").see_other();
+    }
+    let submsg_id = form.submsgid.unwrap_or(0);
+    let client = &data.db_client;
+    if let Err(e) = client
+        .add_staff_note(form.msgid, submsg_id, &staff.username, note)
+        .await
+    {
+        log::error!("Failed to add staff note: {:?}", e);
+        return web::Redirect::to("/error?error_code=500").see_other();
+    }
+    log::info!(
+        "{} added a note to post {}/{}",
+        staff.username,
+        form.msgid,
+        submsg_id
+    );
+    client
+        .log_action(&NewAuditEntry {
+            reason: note,
+            ..NewAuditEntry::on_post(&staff.username, AuditAction::AddNote, form.msgid, submsg_id)
+        })
+        .await;
+    web::Redirect::to(format!(
+        "/dashboard?flagged_type=notes&msgid={}&submsgid={}",
+        form.msgid, submsg_id
+    ))
+    .see_other()
+}
+
+/// Handler for dismissing reports, which removes the post from report queue
+#[post("/dismiss")]
+pub async fn dismiss_reports(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<DeletionForm>,
+) -> impl Responder {
+    let (staff, _) = match authorize_on_post(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::Delete,
+        form.msgid,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(redirect) => return redirect.see_other(),
+    };
+    log::info!(
+        "{} dismissed reports on post {}/{}",
+        staff.username,
+        form.msgid,
+        form.submsgid.unwrap_or(0)
+    );
+    let client = &data.db_client;
+    let submsg_id = form.submsgid.unwrap_or(0);
+    let reason = top_report_reason(
+        client
+            .get_post_reports(form.msgid, submsg_id)
+            .await
+            .unwrap_or_default(),
+    );
+    client
+        .log_action(&NewAuditEntry {
+            reason: &reason,
+            ..NewAuditEntry::on_post(
+                &staff.username,
+                AuditAction::DismissReports,
+                form.msgid,
+                submsg_id,
+            )
+        })
+        .await;
+    client.dismiss_reports(form.msgid, submsg_id).await;
+    match form.submsgid {
+        Some(_) => web::Redirect::to("/dashboard?flagged_type=submsg").see_other(),
+        None => web::Redirect::to("/dashboard?flagged_type=msg").see_other(),
+    }
+}
+
+/// Handler for deleting a reported post and proceeding to ban its poster.
+/// The ban form is prefilled with poster identity, board and the most common report reason
+#[post("/delete_and_ban")]
+pub async fn delete_and_ban(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<DeletionForm>,
+) -> impl Responder {
+    let (staff, board) = match authorize_on_post(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::Delete,
+        form.msgid,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(redirect) => return redirect.see_other(),
+    };
+    if !staff.can(Permission::Ban, Some(&board)) {
+        return web::Redirect::to("/error?error_code=403").see_other();
+    }
+    let client = &data.db_client;
+    let (poster_hash, board) = match form.submsgid {
+        Some(submsgid) => match client.get_single_submessage(form.msgid, submsgid).await {
+            Ok(m) => (m.poster_hash, m.board),
+            Err(_) => return web::Redirect::to("/error?error_code=404").see_other(),
+        },
+        None => match client.get_single_message(form.msgid).await {
+            Ok(m) => (m.poster_hash, m.board),
+            Err(_) => return web::Redirect::to("/error?error_code=404").see_other(),
+        },
+    };
+    let submsg_id = form.submsgid.unwrap_or(0);
+    let reason = top_report_reason(
+        client
+            .get_post_reports(form.msgid, submsg_id)
+            .await
+            .unwrap_or_default(),
+    );
+
+    log::info!(
+        "{} deleted post {}/{}",
+        staff.username,
+        form.msgid,
+        submsg_id
+    );
+    client
+        .log_action(&NewAuditEntry {
+            reason: &reason,
+            ..NewAuditEntry::on_post(&staff.username, AuditAction::Delete, form.msgid, submsg_id)
+        })
+        .await;
+    match form.submsgid {
+        Some(submsgid) => client.delete_submsg(form.msgid, submsgid).await,
+        None => client.delete_msg(form.msgid).await,
+    };
+    web::Redirect::to(format!(
+        "/dashboard?flagged_type=bans&address={}&board={}&reason={}",
+        poster_hash,
+        board,
+        urlencoding::encode(&reason)
+    ))
+    .see_other()
+}
+
+/// Handler for restoring messages from trash
+#[post("/restore")]
+pub async fn restore_msg(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<DeletionForm>,
+) -> impl Responder {
+    let (staff, _) = match authorize_on_post(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::Delete,
+        form.msgid,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(redirect) => return redirect.see_other(),
+    };
+    log::info!(
+        "{} restored post {}/{}",
+        staff.username,
+        form.msgid,
+        form.submsgid.unwrap_or(0)
+    );
+    let client = &data.db_client;
+    client
+        .log_action(&NewAuditEntry::on_post(
+            &staff.username,
+            AuditAction::Restore,
+            form.msgid,
+            form.submsgid.unwrap_or(0),
+        ))
+        .await;
+    if let Some(submsgid) = form.submsgid {
+        client.restore_submsg(form.msgid, submsgid).await;
     } else {
-        client.delete_msg(query.msgid).await;
-        web::Redirect::to("/dashboard?flagged_type=msg").see_other()
+        client.restore_msg(form.msgid).await;
+    }
+    web::Redirect::to("/dashboard?flagged_type=trash").see_other()
+}
+
+/// Handler for banning posters
+#[post("/ban")]
+pub async fn ban_poster(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<BanForm>,
+) -> impl Responder {
+    let board = Some(form.board.as_str()).filter(|b| !b.is_empty());
+    let staff = match authorize(&data, &session, &form.csrf_token, Permission::Ban, board).await {
+        Ok(s) => s,
+        Err(redirect) => return redirect.see_other(),
+    };
+
+    // single addresses are banned by hash, so that they are never stored
+    let address = form.address.trim();
+    let target = if let Ok(range) = address.parse::<IpNet>() {
+        BanTarget::Range(range)
+    } else if let Ok(ip) = address.parse::<IpAddr>() {
+        BanTarget::Hash(crate::routes::hash_ip(
+            &ip.to_canonical(),
+            &data.config.ip_hash_salt,
+        ))
+    } else if address.len() == 64 && address.chars().all(|c| c.is_ascii_hexdigit()) {
+        BanTarget::Hash(address.to_lowercase())
+    } else {
+        return web::Redirect::to("/error?error_code=400").see_other();
+    };
+    if !form.board.is_empty() && !data.config.boards.contains_key(&form.board) {
+        return web::Redirect::to("/error?error_code=404").see_other();
+    }
+    let expires_at = match form.duration_days {
+        0 => None,
+        d => Some(crate::html_proc::since_epoch() + d as i64 * 86400),
+    };
+
+    match data
+        .db_client
+        .create_ban(
+            &target,
+            &form.board,
+            form.reason.trim(),
+            expires_at,
+            form.shadow.is_some(),
+        )
+        .await
+    {
+        Ok(ban_id) => {
+            log::info!("{} created ban {}", staff.username, ban_id);
+            let target = match &target {
+                BanTarget::Hash(h) => h.clone(),
+                BanTarget::Range(r) => r.trunc().to_string(),
+            };
+            let action = match form.shadow {
+                Some(_) => AuditAction::ShadowBan,
+                None => AuditAction::Ban,
+            };
+            data.db_client
+                .log_action(&NewAuditEntry {
+                    board: &form.board,
+                    target: &target,
+                    reason: form.reason.trim(),
+                    ..NewAuditEntry::new(&staff.username, action)
+                })
+                .await;
+        }
+        Err(e) => {
+            log::error!("Failed to create ban: {:?}", e);
+            return web::Redirect::to("/error?error_code=500").see_other();
+        }
+    };
+    web::Redirect::to("/dashboard?flagged_type=bans").see_other()
+}
+
+/// Handler for lifting bans
+#[post("/unban")]
+pub async fn lift_ban(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<UnbanForm>,
+) -> impl Responder {
+    let staff = match authorize(&data, &session, &form.csrf_token, Permission::Ban, None).await {
+        Ok(s) => s,
+        Err(redirect) => return redirect.see_other(),
+    };
+    log::info!("{} lifted ban {}", staff.username, form.ban_id);
+    data.db_client.lift_ban(form.ban_id).await;
+    data.db_client
+        .log_action(&NewAuditEntry {
+            target: &format!("ban {}", form.ban_id),
+            ..NewAuditEntry::new(&staff.username, AuditAction::LiftBan)
+        })
+        .await;
+    web::Redirect::to("/dashboard?flagged_type=bans").see_other()
+}
+
+/// Handler for creating staff accounts
+#[post("/staff/create")]
+pub async fn create_staff(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<StaffForm>,
+) -> impl Responder {
+    let staff = match authorize(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::ManageStaff,
+        None,
+    )
+    .await
+    {
+        Ok(s) => s,
+        Err(redirect) => return redirect.see_other(),
+    };
+    let username = form.username.trim();
+    let role = match Role::from_name(&form.role) {
+        Some(r) => r,
+        None => return web::Redirect::to("/error?error_code=400").see_other(),
+    };
+    if username.is_empty() || form.password.is_empty() {
+        return web::Redirect::to("/error?error_code=400").see_other();
+    }
+    let boards: Vec<&str> = form
+        .boards
+        .split(',')
+        .map(|b| b.trim())
+        .filter(|b| !b.is_empty())
+        .collect();
+    if boards.iter().any(|b| !data.config.boards.contains_key(*b)) {
+        return web::Redirect::to("/error?error_code=404").see_other();
+    }
+    let password = form.password.clone();
+    let password_hash = match web::block(move || staff::hash_password(&password)).await {
+        Ok(h) => h,
+        Err(_) => return web::Redirect::to("/error?error_code=500").see_other(),
+    };
+
+    match data
+        .db_client
+        .create_staff(username, &password_hash, role, &boards.join(","))
+        .await
+    {
+        Ok(staff_id) => {
+            log::info!(
+                "{} created {} account {} ({})",
+                staff.username,
+                role.as_str(),
+                username,
+                staff_id
+            );
+            data.db_client
+                .log_action(&NewAuditEntry {
+                    target: &format!("{} ({})", username, role.title()),
+                    ..NewAuditEntry::new(&staff.username, AuditAction::CreateStaff)
+                })
+                .await;
+        }
+        Err(e) => {
+            // most likely a taken username
+            log::error!("Failed to create staff account: {:?}", e);
+            return web::Redirect::to("/error?error_code=400").see_other();
+        }
+    };
+    web::Redirect::to("/dashboard?flagged_type=staff").see_other()
+}
+
+/// Handler for removing staff accounts
+#[post("/staff/remove")]
+pub async fn remove_staff(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<StaffRemovalForm>,
+) -> impl Responder {
+    let staff = match authorize(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::ManageStaff,
+        None,
+    )
+    .await
+    {
+        Ok(s) => s,
+        Err(redirect) => return redirect.see_other(),
+    };
+    if form.staff_id == staff.staff_id {
+        return web::Redirect::to("/error?error_code=400").see_other();
+    }
+    let removed = match data.db_client.get_staff(form.staff_id).await {
+        Ok(s) => s,
+        Err(_) => return web::Redirect::to("/error?error_code=404").see_other(),
+    };
+    match data.db_client.remove_staff(form.staff_id).await {
+        Ok(true) => {
+            log::info!(
+                "{} removed staff account {}",
+                staff.username,
+                removed.username
+            );
+            data.db_client
+                .log_action(&NewAuditEntry {
+                    target: &removed.username,
+                    ..NewAuditEntry::new(&staff.username, AuditAction::RemoveStaff)
+                })
+                .await;
+        }
+        Ok(false) => return web::Redirect::to("/error?error_code=400").see_other(),
+        Err(e) => {
+            log::error!("Failed to remove staff account: {:?}", e);
+            return web::Redirect::to("/error?error_code=500").see_other();
+        }
+    };
+    web::Redirect::to("/dashboard?flagged_type=staff").see_other()
+}
+
+/// Handler for adding banlist entries
+#[post("/banlist/add")]
+pub async fn add_banlist_entry(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<BanlistForm>,
+) -> impl Responder {
+    let staff = match authorize(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::ManageBoards,
+        None,
+    )
+    .await
+    {
+        Ok(s) => s,
+        Err(redirect) => return redirect.see_other(),
+    };
+    let pattern = form.pattern.trim();
+    let scope: Vec<PostField> = [
+        (&form.scope_author, PostField::Author),
+        (&form.scope_message, PostField::Message),
+        (&form.scope_filename, PostField::Filename),
+    ]
+    .into_iter()
+    .filter(|(checked, _)| checked.is_some())
+    .map(|(_, scope)| scope)
+    .collect();
+    let boards: Vec<String> = form
+        .boards
+        .split(',')
+        .map(|b| b.trim().to_string())
+        .filter(|b| !b.is_empty())
+        .collect();
+    if pattern.is_empty() || scope.is_empty() {
+        return web::Redirect::to("/error?error_code=400").see_other();
+    }
+    if boards.iter().any(|b| !data.config.boards.contains_key(b)) {
+        return web::Redirect::to("/error?error_code=404").see_other();
+    }
+
+    let mut entries = data.banlist.get().entries.clone();
+    entries.push(BanlistEntry {
+        pattern: pattern.to_string(),
+        scope,
+        boards,
+    });
+    if let Err(e) = data.banlist.save(entries) {
+        log::error!("Failed to save banlist: {}", e);
+        return web::Redirect::to("/error?error_code=400").see_other();
+    }
+    log::info!("{} added '{}' to banlist", staff.username, pattern);
+    data.db_client
+        .log_action(&NewAuditEntry {
+            target: &format!("banlist: added '{}'", pattern),
+            ..NewAuditEntry::new(&staff.username, AuditAction::EditConfig)
+        })
+        .await;
+    web::Redirect::to("/dashboard?flagged_type=banlist").see_other()
+}
+
+/// Handler for removing banlist entries
+#[post("/banlist/remove")]
+pub async fn remove_banlist_entry(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<BanlistRemovalForm>,
+) -> impl Responder {
+    let staff = match authorize(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::ManageBoards,
+        None,
+    )
+    .await
+    {
+        Ok(s) => s,
+        Err(redirect) => return redirect.see_other(),
+    };
+    let mut entries = data.banlist.get().entries.clone();
+    if form.index >= entries.len() {
+        return web::Redirect::to("/error?error_code=404").see_other();
+    }
+    let removed = entries.remove(form.index);
+    if let Err(e) = data.banlist.save(entries) {
+        log::error!("Failed to save banlist: {}", e);
+        return web::Redirect::to("/error?error_code=500").see_other();
+    }
+    log::info!(
+        "{} removed '{}' from banlist",
+        staff.username,
+        removed.pattern
+    );
+    data.db_client
+        .log_action(&NewAuditEntry {
+            target: &format!("banlist: removed '{}'", removed.pattern),
+            ..NewAuditEntry::new(&staff.username, AuditAction::EditConfig)
+        })
+        .await;
+    web::Redirect::to("/dashboard?flagged_type=banlist").see_other()
+}
+
+/// Handler for pinning and locking threads
+#[post("/thread_state")]
+pub async fn set_thread_state(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<ThreadStateForm>,
+) -> impl Responder {
+    let (staff, _) = match authorize_on_post(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::Delete,
+        form.msgid,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(redirect) => return redirect.see_other(),
+    };
+    let client = &data.db_client;
+    let action = match AuditAction::from_name(&form.action) {
+        Some(a @ (AuditAction::Sticky | AuditAction::Unsticky)) => {
+            client
+                .set_sticky(form.msgid, a == AuditAction::Sticky)
+                .await;
+            a
+        }
+        Some(a @ (AuditAction::Lock | AuditAction::Unlock)) => {
+            client.set_locked(form.msgid, a == AuditAction::Lock).await;
+            a
+        }
+        _ => return web::Redirect::to("/error?error_code=400").see_other(),
+    };
+    log::info!(
+        "{} changed state of thread {}: {}",
+        staff.username,
+        form.msgid,
+        form.action
+    );
+    client
+        .log_action(&NewAuditEntry::on_post(
+            &staff.username,
+            action,
+            form.msgid,
+            0,
+        ))
+        .await;
+    web::Redirect::to("/dashboard?flagged_type=threads").see_other()
+}
+
+/// Handler for moving threads between boards
+#[post("/move_thread")]
+pub async fn move_thread(
+    data: web::Data<ApplicationState<'_>>,
+    session: actix_session::Session,
+    form: web::Form<MoveThreadForm>,
+) -> impl Responder {
+    // the thread has to be moderated on both boards
+    let (staff, board) = match authorize_on_post(
+        &data,
+        &session,
+        &form.csrf_token,
+        Permission::Delete,
+        form.msgid,
+    )
+    .await
+    {
+        Ok((s, b)) if s.can(Permission::Delete, Some(&form.board)) => (s, b),
+        Ok(_) => return web::Redirect::to("/error?error_code=403").see_other(),
+        Err(redirect) => return redirect.see_other(),
+    };
+    if form.board == board || !data.config.boards.contains_key(&form.board) {
+        return web::Redirect::to("/error?error_code=400").see_other();
+    }
+    let client = &data.db_client;
+    let hard_limit = match data.config.board_settings(&form.board) {
+        Some(settings) => settings.hard_limit.into(),
+        None => return web::Redirect::to("/error?error_code=400").see_other(),
+    };
+    let archive = data.config.boards[&form.board].limit_policy == crate::LimitPolicy::Archive;
+
+    // the notice is a locked thread linking to the moved one, left only on configured boards
+    let old_settings = data.config.board_settings(&board);
+    let body = format!(">>{}\nThread moved to /{}/", form.msgid, form.board);
+    let notice = match (&form.notice, &old_settings) {
+        (Some(_), Some(old_settings)) => Some(crate::db_control::NewPost {
+            board: &board,
+            time: crate::html_proc::since_epoch(),
+            author: &old_settings.default_poster_name,
+            tripcode: "",
+            body: &body,
+            poster_hash: "",
+            attachments: &[],
+            visibility: crate::db_control::PostVisibility::Public,
+        }),
+        _ => None,
+    };
+    match client
+        .move_thread(
+            form.msgid,
+            &form.board,
+            hard_limit,
+            archive,
+            notice.as_ref(),
+        )
+        .await
+    {
+        Ok(true) => (),
+        Ok(false) => return web::Redirect::to("/error?error_code=404").see_other(),
+        Err(e) => {
+            log::error!("Failed to move thread {}: {:?}", form.msgid, e);
+            return web::Redirect::to("/error?error_code=500").see_other();
+        }
     }
+    log::info!(
+        "{} moved thread {} from /{}/ to /{}/",
+        staff.username,
+        form.msgid,
+        board,
+        form.board
+    );
+    client
+        .log_action(&NewAuditEntry {
+            target: &format!("/{}/ → /{}/", board, form.board),
+            ..NewAuditEntry::on_post(&staff.username, AuditAction::Move, form.msgid, 0)
+        })
+        .await;
+    web::Redirect::to("/dashboard?flagged_type=threads").see_other()
 }
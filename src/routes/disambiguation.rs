@@ -1,7 +1,7 @@
 //! Handler for redirecting message links to appropriate boards
 
-use crate::routes::ApplicationState;
-use actix_web::{get, web, Responder};
+use crate::routes::{viewer_hash, ApplicationState};
+use actix_web::{get, web, HttpRequest, Responder};
 
 /// Query option containing msgid-submsgid pair as a string
 #[derive(serde::Deserialize)]
@@ -14,8 +14,10 @@ struct IdPairQueryOptions {
 pub async fn to_msg(
     data: web::Data<ApplicationState<'_>>,
     query: web::Query<IdPairQueryOptions>,
+    req: HttpRequest,
 ) -> impl Responder {
-    let client = data.db_client.lock().await;
+    let client = &data.db_client;
+    let viewer = viewer_hash(&req, &data.config.ip_hash_salt);
     if query.idpair.contains('.') {
         // if both message and submessage are specified
         let parts: Vec<Result<i64, _>> =
@@ -27,9 +29,11 @@ pub async fn to_msg(
         let parts: Vec<i64> = parts.into_iter().map(|x| x.unwrap()).collect();
         let msg = client.get_single_submessage(parts[0], parts[1]).await;
         match msg {
-            Ok(row) => web::Redirect::to(format!("{}/topic/{}#{}", row.board, parts[0], parts[1]))
-                .see_other(),
-            Err(_) => web::Redirect::to("/error?error_code=404").see_other(),
+            Ok(row) if row.visible_to(&viewer) => {
+                web::Redirect::to(format!("{}/topic/{}#{}", row.board, parts[0], parts[1]))
+                    .see_other()
+            }
+            _ => web::Redirect::to("/error?error_code=404").see_other(),
         }
     } else {
         let msgid = query.idpair.parse::<i64>();
@@ -39,8 +43,10 @@ pub async fn to_msg(
         let msgid = msgid.unwrap();
         let msg = client.get_single_message(msgid).await;
         match msg {
-            Ok(row) => web::Redirect::to(format!("{}/topic/{}", row.board, msgid)).see_other(),
-            Err(_) => web::Redirect::to("/error?error_code=404").see_other(),
+            Ok(row) if row.visible_to(&viewer) => {
+                web::Redirect::to(format!("{}/topic/{}", row.board, msgid)).see_other()
+            }
+            _ => web::Redirect::to("/error?error_code=404").see_other(),
         }
     }
 }
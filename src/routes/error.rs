@@ -4,10 +4,78 @@ use actix_web::{get, web, HttpResponse, Responder};
 
 use crate::routes::ApplicationState;
 
-/// Query params containing required error code
+/// Query params containing required error code and, optionally, the reason of a refused request
 #[derive(serde::Deserialize)]
 struct ErrorQuery {
     error_code: Option<i64>,
+    reason: Option<String>,
+}
+
+/// Reasons for refusing posts and other requests, explained to users on the error page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    ThreadLocked,
+    LoginLocked,
+    ThreadCooldown,
+    ReplyCooldown,
+    TooManyLinks,
+    DuplicatePost,
+    BoardFlooded,
+}
+
+impl Rejection {
+    const ALL: [Rejection; 7] = [
+        Rejection::ThreadLocked,
+        Rejection::LoginLocked,
+        Rejection::ThreadCooldown,
+        Rejection::ReplyCooldown,
+        Rejection::TooManyLinks,
+        Rejection::DuplicatePost,
+        Rejection::BoardFlooded,
+    ];
+
+    /// Name of the reason, as passed in error page URLs
+    fn as_str(&self) -> &'static str {
+        match self {
+            Rejection::ThreadLocked => "thread_locked",
+            Rejection::LoginLocked => "login_locked",
+            Rejection::ThreadCooldown => "thread_cooldown",
+            Rejection::ReplyCooldown => "reply_cooldown",
+            Rejection::TooManyLinks => "too_many_links",
+            Rejection::DuplicatePost => "duplicate_post",
+            Rejection::BoardFlooded => "board_flooded",
+        }
+    }
+
+    /// Explanation displayed on the error page
+    fn description(&self) -> &'static str {
+        match self {
+            Rejection::ThreadLocked => "This thread is locked. New replies are not accepted.",
+            Rejection::LoginLocked => {
+                "Too many failed login attempts. Wait a few minutes before trying again."
+            }
+            Rejection::ThreadCooldown => {
+                "You have started a thread on this board recently. Wait a while before starting another one."
+            }
+            Rejection::ReplyCooldown => "You are posting too fast. Wait a few seconds before replying again.",
+            Rejection::TooManyLinks => "Your post contains too many links.",
+            Rejection::DuplicatePost => {
+                "Your post is the same as or very similar to a recent post on this board."
+            }
+            Rejection::BoardFlooded => {
+                "This board is receiving too many posts right now. Try again in a few minutes."
+            }
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Rejection> {
+        Rejection::ALL.into_iter().find(|r| r.as_str() == name)
+    }
+
+    /// Redirect to the error page explaining the rejection
+    pub fn redirect(&self) -> web::Redirect {
+        web::Redirect::to(format!("/error?error_code=403&reason={}", self.as_str()))
+    }
 }
 
 /// Returns the error page with appropriate error displayed
@@ -17,10 +85,16 @@ pub async fn error_page(
     q: web::Query<ErrorQuery>,
 ) -> impl Responder {
     let ecode_unwrapped = q.error_code.unwrap_or(500);
+    let reason = q
+        .reason
+        .as_deref()
+        .and_then(Rejection::from_name)
+        .map(|r| r.description());
     HttpResponse::Ok().body(
         data.formatter
-            .format_into_error(
+            .format_into_error_with_reason(
                 actix_web::http::StatusCode::from_u16(ecode_unwrapped.try_into().unwrap()).unwrap(),
+                reason,
             )
             .await,
     )
@@ -8,8 +8,8 @@ use crate::routes::ApplicationState;
 #[get("/")]
 pub async fn root(data: web::Data<ApplicationState<'_>>) -> impl Responder {
     let mut board_links = Vec::new();
-    for (board_name, desc) in &data.config.boards {
-        board_links.push((board_name, desc));
+    for (board_name, settings) in &data.config.boards {
+        board_links.push((board_name, &settings.description));
     }
 
     HttpResponse::Ok().body(
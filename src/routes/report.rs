@@ -1,7 +1,10 @@
 //! Handler for message reporting
 
-use crate::routes::ApplicationState;
-use actix_web::{get, post, web, HttpResponse, Responder};
+use crate::routes::{ApplicationState, PosterIdentity};
+use actix_web::{get, http::StatusCode, post, web, HttpRequest, HttpResponse, Responder};
+
+/// Max length of comments attached to reports
+const MAX_COMMENT_LENGTH: usize = 500;
 
 /// Query params that specify reported messages
 #[derive(serde::Deserialize)]
@@ -17,6 +20,8 @@ struct ReportCaptchaForm {
     captcha_hash: String,
     id: i64,
     subid: Option<i64>,
+    reason: String,
+    comment: Option<String>,
 }
 
 /// Unified handler for reporting messages and submessages
@@ -29,7 +34,13 @@ pub async fn report_msg(
         sha256::digest(crate::routes::create_new_captcha(data.config.captcha_num_limit).await);
     HttpResponse::Ok().body(
         data.formatter
-            .format_into_report_captcha("".to_string(), captcha_hash, page_data.id, page_data.subid)
+            .format_into_report_captcha(
+                "".to_string(),
+                captcha_hash,
+                page_data.id,
+                page_data.subid,
+                &data.config.report_reasons,
+            )
             .await,
     )
 }
@@ -39,11 +50,12 @@ pub async fn report_msg(
 pub async fn report_process_captcha(
     data: web::Data<ApplicationState<'_>>,
     form: web::Form<ReportCaptchaForm>,
+    req: HttpRequest,
 ) -> impl Responder {
     if form.captcha_hash.clone() != sha256::digest(form.captcha_answer.clone()) {
         return HttpResponse::Ok().body(
             data.formatter
-                .format_into_error(actix_web::http::StatusCode::FORBIDDEN)
+                .format_into_error(StatusCode::FORBIDDEN)
                 .await,
         );
     }
@@ -51,13 +63,62 @@ pub async fn report_process_captcha(
     // delete captcha image after usage
     crate::routes::delete_captcha_image(form.captcha_answer.to_string()).await;
 
-    let client = data.db_client.lock().await;
-    let message_type = match form.subid {
-        Some(_v) => "submsg",
-        None => "msg",
+    let comment = form.comment.as_deref().unwrap_or("").trim();
+    if !data.config.report_reasons.contains(&form.reason) || comment.len() > MAX_COMMENT_LENGTH {
+        return HttpResponse::Ok().body(
+            data.formatter
+                .format_into_error(StatusCode::FORBIDDEN)
+                .await,
+        );
+    }
+
+    // only existing posts can be reported
+    let client = &data.db_client;
+    // reports of unidentified clients can't be de-duplicated, so they are refused
+    let reporter_hash = match PosterIdentity::from_request(&req, &data.config.ip_hash_salt) {
+        Some(p) => p.hash,
+        None => {
+            return HttpResponse::Ok().body(
+                data.formatter
+                    .format_into_error(StatusCode::INTERNAL_SERVER_ERROR)
+                    .await,
+            )
+        }
+    };
+    let exists = match form.subid {
+        Some(subid) => client
+            .get_single_submessage(form.id, subid)
+            .await
+            .is_ok_and(|m| m.visible_to(&reporter_hash)),
+        None => client
+            .get_single_message(form.id)
+            .await
+            .is_ok_and(|m| m.visible_to(&reporter_hash)),
     };
-    client
-        .insert_to_flagged(message_type.to_string(), form.id, form.subid)
-        .await;
+    if !exists {
+        return HttpResponse::Ok().body(
+            data.formatter
+                .format_into_error(StatusCode::NOT_FOUND)
+                .await,
+        );
+    }
+
+    if let Err(e) = client
+        .insert_report(
+            form.id,
+            form.subid.unwrap_or(0),
+            &form.reason,
+            comment,
+            &reporter_hash,
+        )
+        .await
+    {
+        log::error!("Failed to store report: {:?}", e);
+        return HttpResponse::Ok().body(
+            data.formatter
+                .format_into_error(StatusCode::INTERNAL_SERVER_ERROR)
+                .await,
+        );
+    }
     HttpResponse::Ok().body(data.formatter.format_into_report_accepted().await)
 }
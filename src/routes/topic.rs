@@ -1,10 +1,13 @@
 //! Handlers for individual threads' pages
 
 use actix_multipart::form::MultipartForm;
-use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder};
+use actix_web::{get, http::StatusCode, post, web, HttpRequest, HttpResponse, Responder};
 
+use crate::antispam;
 use crate::html_proc;
 use crate::routes::*;
+use crate::rule_file::PostField;
+use crate::tripcode;
 
 /// Responder for individual topics/threads
 #[get("{board}/topic/{message_num}")]
@@ -12,6 +15,7 @@ pub async fn topic(
     data: web::Data<ApplicationState<'_>>,
     info: web::Path<PathInfo>,
     page_data: web::Query<QueryOptions>,
+    req: HttpRequest,
 ) -> impl Responder {
     let message_num = info.message_num.unwrap_or(1);
     if !data.config.boards.contains_key(&info.board) {
@@ -24,10 +28,22 @@ pub async fn topic(
 
     let current_page = page_data.page.unwrap_or(1);
 
-    let client = data.db_client.lock().await;
+    let client = &data.db_client;
+    let viewer = viewer_hash(&req, &data.config.ip_hash_salt);
     let head_msg: String;
+    let replies_closed: bool;
     let head_msg_data = client.get_single_message(message_num).await;
-    if let Ok(d) = head_msg_data {
+    if let Some(d) = head_msg_data.ok().filter(|d| d.visible_to(&viewer)) {
+        // threads moved to another board are served from there
+        if d.board != info.board {
+            return HttpResponse::SeeOther()
+                .append_header((
+                    "Location",
+                    format!("/{}/topic/{}?page={}", d.board, message_num, current_page),
+                ))
+                .finish();
+        }
+        replies_closed = d.archived_at.is_some() || d.locked_at.is_some();
         head_msg = data
             .formatter
             .format_into_message(
@@ -45,22 +61,27 @@ pub async fn topic(
         );
     }
     let mut inserted_submsg = String::from("");
-    for row in client.get_submessages(message_num).await.unwrap() {
+    for row in client.get_submessages(message_num, &viewer).await.unwrap() {
         inserted_submsg.push_str(data.formatter.format_into_submessage(row).await.as_str());
     }
 
-    let captcha_value =
-        sha256::digest(crate::routes::create_new_captcha(data.config.captcha_num_limit).await);
+    // archived and locked threads are read-only, so there's no need for a captcha
+    let captcha_value = match replies_closed {
+        true => None,
+        false => Some(sha256::digest(
+            crate::routes::create_new_captcha(data.config.captcha_num_limit).await,
+        )),
+    };
 
     HttpResponse::Ok().body(
         data.formatter
             .format_into_topic(
-                &data.config.site_name,
+                &data.config,
                 &message_num.to_string(),
                 &head_msg,
                 &inserted_submsg,
                 &info.board.to_string(),
-                Some(&captcha_value),
+                captcha_value.as_ref(),
             )
             .await,
     )
@@ -73,41 +94,89 @@ pub async fn topic_process_form(
     form: MultipartForm<MsgForm>,
     page_data: web::Query<QueryOptions>,
     info: web::Path<PathInfo>,
+    req: HttpRequest,
 ) -> impl Responder {
-    const MAX_AUTHOR_LENGTH: usize = 250;
-    const MAX_MESSAGE_LENGTH: usize = 4000;
-
     let message_num = info.message_num.unwrap_or(1);
-    if !data.config.boards.contains_key(&info.board) {
-        return web::Redirect::to(format!("{}/topic/{}", info.board, message_num)).see_other();
-    }
+    let settings = match data.config.board_settings(&info.board) {
+        Some(s) => s,
+        None => {
+            return web::Redirect::to(format!("{}/topic/{}", info.board, message_num)).see_other()
+        }
+    };
+
+    let client = &data.db_client;
 
-    let client = data.db_client.lock().await;
+    // banned posters are sent to the ban page
+    let poster = match PosterIdentity::from_request(&req, &data.config.ip_hash_salt) {
+        Some(p) => p,
+        None => return web::Redirect::to("/error?error_code=500").see_other(),
+    };
+    let shadow_banned = match check_bans(client, &info.board, &poster).await {
+        Ok(s) => s,
+        Err(redirect) => return redirect.see_other(),
+    };
+
+    // replies to archived and locked threads are not accepted
+    match client.get_single_message(message_num).await {
+        Ok(m) if m.archived_at.is_some() => {
+            return web::Redirect::to("/error?error_code=403").see_other()
+        }
+        Ok(m) if m.locked_at.is_some() => return Rejection::ThreadLocked.redirect().see_other(),
+        Ok(m) if m.board != info.board || !m.visible_to(&poster.hash) => {
+            return web::Redirect::to("/error?error_code=404").see_other()
+        }
+        Ok(_) => (),
+        Err(_) => return web::Redirect::to("/error?error_code=404").see_other(),
+    };
 
     let trimmed_author = form.author.trim();
     let trimmed_message = form.message.trim();
 
     // if fits, push new message into DB
-    if trimmed_author.len() > MAX_AUTHOR_LENGTH
+    if trimmed_author.len() > settings.max_author_length
         || trimmed_message.is_empty()
-        || trimmed_message.len() > MAX_MESSAGE_LENGTH
+        || trimmed_message.len() > settings.max_message_length
     {
         return web::Redirect::to("/error?error_code=403").see_other();
     }
 
-    let filtered_author = match trimmed_author.len() {
-        0 => "Anonymous".to_string(),
-        _ => data.formatter.filter_tags(trimmed_author).await,
+    // "name#password" is split into the name and a tripcode
+    let (name, tripcode) = tripcode::split_author(trimmed_author, &data.config.tripcode_salt);
+    let name = name.trim();
+    let filtered_author = match name.len() {
+        0 => settings.default_poster_name.clone(),
+        _ => data.formatter.filter_tags(name).await,
     };
     let filtered_msg = data.formatter.filter_tags(trimmed_message).await;
 
     // checking for banned words
-    if contains_banned_words(&filtered_author).await
-        || contains_banned_words(&filtered_msg).await
-    {
+    if contains_banned_words(
+        &data.banlist,
+        &info.board,
+        &filtered_author,
+        &filtered_msg,
+        &form.files,
+    ) {
         return web::Redirect::to("/error?error_code=403").see_other();
     }
 
+    // wordfilters are applied to posts that passed the banlist, so they can't hide banned words
+    let filtered_author = match name.len() {
+        0 => filtered_author,
+        _ => rewrite_words(
+            &data.wordfilters,
+            &info.board,
+            PostField::Author,
+            &filtered_author,
+        ),
+    };
+    let filtered_msg = rewrite_words(
+        &data.wordfilters,
+        &info.board,
+        PostField::Message,
+        &filtered_msg,
+    );
+
     // checking for correct captcha
     let hash_true = form.captcha_hash.to_string();
     let hash_sent = sha256::digest(form.captcha_answer.to_string());
@@ -121,35 +190,93 @@ pub async fn topic_process_form(
     // Checking against the last message (to prevent spam)
     if let Ok(last_msg) = client.get_last_submessage(&message_num).await {
         if last_msg.submsg == filtered_msg {
-            return web::Redirect::to("/error?error_code=403").see_other();
+            return Rejection::DuplicatePost.redirect().see_other();
+        }
+    }
+
+    // cooldowns, link limits, near-duplicates and flood limits
+    match antispam::check_post(
+        client,
+        &settings,
+        &info.board,
+        &poster.hash,
+        false,
+        &filtered_msg,
+    )
+    .await
+    {
+        Ok(None) => (),
+        Ok(Some(rejection)) => {
+            log::info!("Rejected post on /{}/: {:?}", info.board, rejection);
+            return rejection.redirect().see_other();
+        }
+        Err(e) => {
+            log::error!("Failed to run anti-spam checks: {:?}", e);
+            return web::Redirect::to("/error?error_code=500").see_other();
         }
     }
 
     // getting time
     let since_epoch = html_proc::since_epoch();
 
-    let filepath_collection = process_files(&form.files).await;
+    let attachments = process_files(
+        &form.files,
+        settings.max_attachments,
+        &settings.allowed_media_types,
+    )
+    .await;
 
-    let submsg_count = client.count_submessages(message_num).await.unwrap();
+    let visibility = match post_visibility(
+        client,
+        &settings,
+        &info.board,
+        &poster,
+        false,
+        shadow_banned,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to check moderation policy: {:?}", e);
+            db_control::discard_new_attachments(&attachments);
+            return web::Redirect::to("/error?error_code=500").see_other();
+        }
+    };
+
+    let new_post = db_control::NewPost {
+        board: &info.board,
+        time: since_epoch,
+        author: &filtered_author,
+        tripcode: &tripcode,
+        body: &filtered_msg,
+        poster_hash: &poster.hash,
+        attachments: &attachments,
+        visibility,
+    };
 
-    client
-        .insert_to_submessages(
+    if let Err(e) = client
+        .create_submessage(
             message_num,
-            submsg_count + 1,
-            &info.board,
-            since_epoch,
-            &filtered_author,
-            &filtered_msg,
-            &filepath_collection,
+            &new_post,
+            form.sage.is_none(),
+            settings.bumplimit.into(),
         )
-        .await;
-
-    if submsg_count < data.config.bumplimit.into() && form.sage.is_none() {
-        client
-            .update_message_activity(since_epoch, message_num)
-            .await;
+        .await
+    {
+        db_control::discard_new_attachments(&attachments);
+        return match e {
+            sqlx::Error::RowNotFound => web::Redirect::to("/error?error_code=404").see_other(),
+            _ => {
+                log::error!("Failed to create submessage: {:?}", e);
+                web::Redirect::to("/error?error_code=500").see_other()
+            }
+        };
     }
 
+    if visibility == db_control::PostVisibility::Held {
+        return web::Redirect::to(format!("/awaiting_approval?board={}", info.board)).see_other();
+    }
     web::Redirect::to(format!(
         "/{}/topic/{}{}",
         info.board,
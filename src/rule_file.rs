@@ -0,0 +1,186 @@
+//! Rule files in YAML format (the banlist and wordfilters), shared between request handlers.
+//! Rules are compiled once and recompiled only when their file changes;
+//! changes that don't compile are rejected and the previous rules stay in use.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+/// How often rule files are checked for changes
+const RELOAD_INTERVAL_SECS: u64 = 5;
+
+/// Errors that can happen while loading or saving a rule file
+#[derive(Debug)]
+pub enum RuleFileError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+    Regex(regex::Error),
+}
+
+impl std::fmt::Display for RuleFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleFileError::Io(e) => write!(f, "I/O error: {}", e),
+            RuleFileError::Yaml(e) => write!(f, "malformed YAML: {}", e),
+            RuleFileError::Regex(e) => write!(f, "invalid pattern: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for RuleFileError {
+    fn from(e: std::io::Error) -> Self {
+        RuleFileError::Io(e)
+    }
+}
+
+impl From<serde_yaml::Error> for RuleFileError {
+    fn from(e: serde_yaml::Error) -> Self {
+        RuleFileError::Yaml(e)
+    }
+}
+
+impl From<regex::Error> for RuleFileError {
+    fn from(e: regex::Error) -> Self {
+        RuleFileError::Regex(e)
+    }
+}
+
+/// Parts of a post a rule applies to
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PostField {
+    Author,
+    Message,
+    Filename,
+}
+
+impl PostField {
+    /// Name of the field, as written in rule files
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostField::Author => "author",
+            PostField::Message => "message",
+            PostField::Filename => "filename",
+        }
+    }
+}
+
+/// Fields a rule applies to when its file doesn't list them
+pub fn default_fields() -> Vec<PostField> {
+    vec![PostField::Author, PostField::Message]
+}
+
+/// Compiles a single pattern of a rule. Patterns are case-insensitive
+pub fn compile_pattern(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+}
+
+/// Compiled contents of a rule file, which lists rules of type `Rule`
+pub trait RuleFile: Sized + Send + Sync + 'static {
+    type Rule: DeserializeOwned;
+
+    /// What the rules are called in log messages
+    const NAME: &'static str;
+
+    /// Compiles rules. Fails if any of the patterns is invalid
+    fn compile(rules: Vec<Self::Rule>) -> Result<Self, regex::Error>;
+
+    /// Number of compiled rules
+    fn len(&self) -> usize;
+
+    /// Reads and compiles a rule file
+    fn load(path: &std::path::Path) -> Result<Self, RuleFileError> {
+        let raw_rules = std::fs::read_to_string(path)?;
+        // a file with nothing but comments is an empty list
+        let rules: Option<Vec<Self::Rule>> = serde_yaml::from_str(&raw_rules)?;
+        Ok(Self::compile(rules.unwrap_or_default())?)
+    }
+}
+
+/// Rules loaded from a file, along with the file itself
+pub struct WatchedFile<T> {
+    path: PathBuf,
+    current: RwLock<Arc<T>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl<T: RuleFile> WatchedFile<T> {
+    /// Loads the rules. If they can't be loaded, there are no rules until the file is fixed
+    pub fn new(path: PathBuf) -> WatchedFile<T> {
+        let rules = match T::load(&path) {
+            Ok(r) => r,
+            Err(RuleFileError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("{} doesn't exist, no {} in use", path.display(), T::NAME);
+                T::compile(Vec::new()).unwrap()
+            }
+            Err(e) => {
+                log::error!("Failed to load {}: {}", path.display(), e);
+                T::compile(Vec::new()).unwrap()
+            }
+        };
+        let modified = file_modified(&path);
+        WatchedFile {
+            path,
+            current: RwLock::new(Arc::new(rules)),
+            modified: Mutex::new(modified),
+        }
+    }
+
+    /// Returns the rules currently in use
+    pub fn get(&self) -> Arc<T> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Reloads the rules if their file was changed since it was last loaded.
+    /// Invalid changes are logged and ignored
+    pub fn reload_if_changed(&self) {
+        let modified = file_modified(&self.path);
+        let mut last_modified = self.modified.lock().unwrap();
+        if modified == *last_modified {
+            return;
+        }
+        *last_modified = modified;
+        match T::load(&self.path) {
+            Ok(r) => {
+                log::info!("Reloaded {} ({} entries)", T::NAME, r.len());
+                *self.current.write().unwrap() = Arc::new(r);
+            }
+            Err(e) => log::error!(
+                "Failed to reload {}, keeping the previous {}: {}",
+                self.path.display(),
+                T::NAME,
+                e
+            ),
+        }
+    }
+
+    /// Writes `contents` to the file and starts using `rules`, which were compiled from them
+    pub fn replace(&self, rules: T, contents: &str) -> Result<(), RuleFileError> {
+        let mut last_modified = self.modified.lock().unwrap();
+        std::fs::write(&self.path, contents)?;
+        *last_modified = file_modified(&self.path);
+        *self.current.write().unwrap() = Arc::new(rules);
+        Ok(())
+    }
+}
+
+/// Returns the modification time of a file, if it exists
+fn file_modified(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Starts periodic checks of a rule file for changes
+pub fn watch<T: RuleFile>(file: Arc<WatchedFile<T>>) {
+    actix_web::rt::spawn(async move {
+        let mut interval =
+            actix_web::rt::time::interval(std::time::Duration::from_secs(RELOAD_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            file.reload_if_changed();
+        }
+    });
+}
@@ -0,0 +1,297 @@
+//! Tripcodes let posters prove their identity without accounts. An author name like `name#password`
+//! gets a classic tripcode (`!` and 10 characters), compatible with other imageboards, and
+//! `name##password` gets a secure tripcode (`!!` and 15 characters), which also depends on
+//! `tripcode_salt` and can't be cracked without knowing it.
+//! Classic tripcodes are computed with the traditional DES-based `crypt(3)`, implemented below.
+
+/// Characters used in `crypt(3)` output, also used for secure tripcodes
+const CRYPT_ALPHABET: &[u8; 64] =
+    b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Length of secure tripcodes, without the `!!` prefix
+const SECURE_TRIPCODE_LENGTH: usize = 15;
+
+/// Splits author name from the password of its tripcode and computes the tripcode.
+/// Returns the name and either a tripcode (with `!` or `!!` prefix) or an empty string
+pub fn split_author<'a>(author: &'a str, salt: &str) -> (&'a str, String) {
+    let (name, password) = match author.split_once('#') {
+        Some(parts) => parts,
+        None => return (author, String::new()),
+    };
+    let tripcode = match password.strip_prefix('#') {
+        Some("") => String::new(),
+        Some(secure_password) => format!("!!{}", secure_tripcode(secure_password, salt)),
+        None if password.is_empty() => String::new(),
+        None => format!("!{}", classic_tripcode(password)),
+    };
+    (name, tripcode)
+}
+
+/// Computes a classic tripcode, the same way as most imageboards do for ASCII passwords
+fn classic_tripcode(password: &str) -> String {
+    // passwords are HTML-escaped before hashing on other imageboards
+    let password = password
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    let bytes = password.as_bytes();
+
+    // salt is taken from the 2nd and 3rd characters of the password, padded with "H."
+    let padded = [bytes, b"H."].concat();
+    let mut salt = [padded[1], padded[2]];
+    for c in salt.iter_mut() {
+        *c = match *c {
+            b':'..=b'@' => *c - b':' + b'A',
+            b'['..=b'`' => *c - b'[' + b'a',
+            b'.'..=b'z' => *c,
+            _ => b'.',
+        };
+    }
+
+    let hash = des_crypt(bytes, salt);
+    String::from_utf8_lossy(&hash[3..]).into_owned()
+}
+
+/// Computes a secure tripcode from a salted SHA-256 hash of the password
+fn secure_tripcode(password: &str, salt: &str) -> String {
+    let digest = sha256::digest(format!("{}##{}", salt, password));
+    let mut bits = digest
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    (0..SECURE_TRIPCODE_LENGTH)
+        .map(|_| {
+            let index = (0..6).fold(0, |acc, _| (acc << 1) | bits.next().unwrap());
+            CRYPT_ALPHABET[index as usize] as char
+        })
+        .collect()
+}
+
+/// Initial permutation
+const IP: [u8; 64] = [
+    58, 50, 42, 34, 26, 18, 10, 2, 60, 52, 44, 36, 28, 20, 12, 4, 62, 54, 46, 38, 30, 22, 14, 6,
+    64, 56, 48, 40, 32, 24, 16, 8, 57, 49, 41, 33, 25, 17, 9, 1, 59, 51, 43, 35, 27, 19, 11, 3, 61,
+    53, 45, 37, 29, 21, 13, 5, 63, 55, 47, 39, 31, 23, 15, 7,
+];
+
+/// Final permutation, inverse of `IP`
+const FP: [u8; 64] = [
+    40, 8, 48, 16, 56, 24, 64, 32, 39, 7, 47, 15, 55, 23, 63, 31, 38, 6, 46, 14, 54, 22, 62, 30,
+    37, 5, 45, 13, 53, 21, 61, 29, 36, 4, 44, 12, 52, 20, 60, 28, 35, 3, 43, 11, 51, 19, 59, 27,
+    34, 2, 42, 10, 50, 18, 58, 26, 33, 1, 41, 9, 49, 17, 57, 25,
+];
+
+/// Key permutation into C and D halves
+const PC1_C: [u8; 28] = [
+    57, 49, 41, 33, 25, 17, 9, 1, 58, 50, 42, 34, 26, 18, 10, 2, 59, 51, 43, 35, 27, 19, 11, 3, 60,
+    52, 44, 36,
+];
+const PC1_D: [u8; 28] = [
+    63, 55, 47, 39, 31, 23, 15, 7, 62, 54, 46, 38, 30, 22, 14, 6, 61, 53, 45, 37, 29, 21, 13, 5,
+    28, 20, 12, 4,
+];
+
+/// Left rotations of C and D before every round
+const SHIFTS: [u8; 16] = [1, 1, 2, 2, 2, 2, 2, 2, 1, 2, 2, 2, 2, 2, 2, 1];
+
+/// Selection of round key bits from C and D halves
+const PC2_C: [u8; 24] = [
+    14, 17, 11, 24, 1, 5, 3, 28, 15, 6, 21, 10, 23, 19, 12, 4, 26, 8, 16, 7, 27, 20, 13, 2,
+];
+const PC2_D: [u8; 24] = [
+    41, 52, 31, 37, 47, 55, 30, 40, 51, 45, 33, 48, 44, 49, 39, 56, 34, 53, 46, 42, 50, 36, 29, 32,
+];
+
+/// Expansion of the right half, perturbed by the salt
+const E: [u8; 48] = [
+    32, 1, 2, 3, 4, 5, 4, 5, 6, 7, 8, 9, 8, 9, 10, 11, 12, 13, 12, 13, 14, 15, 16, 17, 16, 17, 18,
+    19, 20, 21, 20, 21, 22, 23, 24, 25, 24, 25, 26, 27, 28, 29, 28, 29, 30, 31, 32, 1,
+];
+
+/// Permutation of S-box output
+const P: [u8; 32] = [
+    16, 7, 20, 21, 29, 12, 28, 17, 1, 15, 23, 26, 5, 18, 31, 10, 2, 8, 24, 14, 32, 27, 3, 9, 19,
+    13, 30, 6, 22, 11, 4, 25,
+];
+
+const S: [[u8; 64]; 8] = [
+    [
+        14, 4, 13, 1, 2, 15, 11, 8, 3, 10, 6, 12, 5, 9, 0, 7, 0, 15, 7, 4, 14, 2, 13, 1, 10, 6, 12,
+        11, 9, 5, 3, 8, 4, 1, 14, 8, 13, 6, 2, 11, 15, 12, 9, 7, 3, 10, 5, 0, 15, 12, 8, 2, 4, 9,
+        1, 7, 5, 11, 3, 14, 10, 0, 6, 13,
+    ],
+    [
+        15, 1, 8, 14, 6, 11, 3, 4, 9, 7, 2, 13, 12, 0, 5, 10, 3, 13, 4, 7, 15, 2, 8, 14, 12, 0, 1,
+        10, 6, 9, 11, 5, 0, 14, 7, 11, 10, 4, 13, 1, 5, 8, 12, 6, 9, 3, 2, 15, 13, 8, 10, 1, 3, 15,
+        4, 2, 11, 6, 7, 12, 0, 5, 14, 9,
+    ],
+    [
+        10, 0, 9, 14, 6, 3, 15, 5, 1, 13, 12, 7, 11, 4, 2, 8, 13, 7, 0, 9, 3, 4, 6, 10, 2, 8, 5,
+        14, 12, 11, 15, 1, 13, 6, 4, 9, 8, 15, 3, 0, 11, 1, 2, 12, 5, 10, 14, 7, 1, 10, 13, 0, 6,
+        9, 8, 7, 4, 15, 14, 3, 11, 5, 2, 12,
+    ],
+    [
+        7, 13, 14, 3, 0, 6, 9, 10, 1, 2, 8, 5, 11, 12, 4, 15, 13, 8, 11, 5, 6, 15, 0, 3, 4, 7, 2,
+        12, 1, 10, 14, 9, 10, 6, 9, 0, 12, 11, 7, 13, 15, 1, 3, 14, 5, 2, 8, 4, 3, 15, 0, 6, 10, 1,
+        13, 8, 9, 4, 5, 11, 12, 7, 2, 14,
+    ],
+    [
+        2, 12, 4, 1, 7, 10, 11, 6, 8, 5, 3, 15, 13, 0, 14, 9, 14, 11, 2, 12, 4, 7, 13, 1, 5, 0, 15,
+        10, 3, 9, 8, 6, 4, 2, 1, 11, 10, 13, 7, 8, 15, 9, 12, 5, 6, 3, 0, 14, 11, 8, 12, 7, 1, 14,
+        2, 13, 6, 15, 0, 9, 10, 4, 5, 3,
+    ],
+    [
+        12, 1, 10, 15, 9, 2, 6, 8, 0, 13, 3, 4, 14, 7, 5, 11, 10, 15, 4, 2, 7, 12, 9, 5, 6, 1, 13,
+        14, 0, 11, 3, 8, 9, 14, 15, 5, 2, 8, 12, 3, 7, 0, 4, 10, 1, 13, 11, 6, 4, 3, 2, 12, 9, 5,
+        15, 10, 11, 14, 1, 7, 6, 0, 8, 13,
+    ],
+    [
+        4, 11, 2, 14, 15, 0, 8, 13, 3, 12, 9, 7, 5, 10, 6, 1, 13, 0, 11, 7, 4, 9, 1, 10, 14, 3, 5,
+        12, 2, 15, 8, 6, 1, 4, 11, 13, 12, 3, 7, 14, 10, 15, 6, 8, 0, 5, 9, 2, 6, 11, 13, 8, 1, 4,
+        10, 7, 9, 5, 0, 15, 14, 2, 3, 12,
+    ],
+    [
+        13, 2, 8, 4, 6, 15, 11, 1, 10, 9, 3, 14, 5, 0, 12, 7, 1, 15, 13, 8, 10, 3, 7, 4, 12, 5, 6,
+        11, 0, 14, 9, 2, 7, 11, 4, 1, 9, 12, 14, 2, 0, 6, 10, 13, 15, 3, 5, 8, 2, 1, 14, 7, 4, 10,
+        8, 13, 15, 12, 9, 0, 3, 5, 6, 11,
+    ],
+];
+
+/// Traditional `crypt(3)`: 25 rounds of salted DES applied to a zero block, keyed with
+/// the first 8 characters of the password. Returns 2 salt characters and 11 hash characters
+fn des_crypt(password: &[u8], salt: [u8; 2]) -> [u8; 13] {
+    // every character gives 7 bits of the key, followed by a parity bit left at zero
+    let mut key = [0u8; 64];
+    for (i, c) in password.iter().take_while(|c| **c != 0).take(8).enumerate() {
+        for j in 0..7 {
+            key[i * 8 + j] = (c >> (6 - j)) & 1;
+        }
+    }
+
+    // round keys
+    let mut c: Vec<u8> = PC1_C.iter().map(|&i| key[i as usize - 1]).collect();
+    let mut d: Vec<u8> = PC1_D.iter().map(|&i| key[i as usize - 1]).collect();
+    let mut round_keys = [[0u8; 48]; 16];
+    for (round_key, &shift) in round_keys.iter_mut().zip(SHIFTS.iter()) {
+        c.rotate_left(shift as usize);
+        d.rotate_left(shift as usize);
+        for j in 0..24 {
+            round_key[j] = c[PC2_C[j] as usize - 1];
+            round_key[j + 24] = d[PC2_D[j] as usize - 28 - 1];
+        }
+    }
+
+    // every bit of the salt swaps a pair of expansion entries
+    let mut expansion = E;
+    for (i, &s) in salt.iter().enumerate() {
+        let value = CRYPT_ALPHABET.iter().position(|&a| a == s).unwrap_or(0);
+        for j in 0..6 {
+            if (value >> j) & 1 == 1 {
+                expansion.swap(6 * i + j, 6 * i + j + 24);
+            }
+        }
+    }
+
+    let mut block = [0u8; 66];
+    for _ in 0..25 {
+        let permuted: Vec<u8> = IP.iter().map(|&i| block[i as usize - 1]).collect();
+        let (mut left, mut right) = ([0u8; 32], [0u8; 32]);
+        left.copy_from_slice(&permuted[..32]);
+        right.copy_from_slice(&permuted[32..]);
+        for round_key in round_keys.iter() {
+            let mut pre_s = [0u8; 48];
+            for j in 0..48 {
+                pre_s[j] = right[expansion[j] as usize - 1] ^ round_key[j];
+            }
+            let mut f = [0u8; 32];
+            for (j, sbox) in S.iter().enumerate() {
+                let b = &pre_s[6 * j..6 * j + 6];
+                let index =
+                    (b[0] << 5) | (b[5] << 4) | (b[1] << 3) | (b[2] << 2) | (b[3] << 1) | b[4];
+                let k = sbox[index as usize];
+                for bit in 0..4 {
+                    f[4 * j + bit] = (k >> (3 - bit)) & 1;
+                }
+            }
+            let previous_right = right;
+            for j in 0..32 {
+                right[j] = left[j] ^ f[P[j] as usize - 1];
+            }
+            left = previous_right;
+        }
+        let swapped: Vec<u8> = right.iter().chain(left.iter()).copied().collect();
+        for (j, &i) in FP.iter().enumerate() {
+            block[j] = swapped[i as usize - 1];
+        }
+    }
+
+    let mut result = [0u8; 13];
+    result[..2].copy_from_slice(&salt);
+    for i in 0..11 {
+        let index = block[6 * i..6 * i + 6]
+            .iter()
+            .fold(0, |acc, &bit| (acc << 1) | bit);
+        result[i + 2] = CRYPT_ALPHABET[index as usize];
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classic_tripcodes_match_other_imageboards() {
+        assert_eq!(classic_tripcode("faggot"), "Ep8pui8Vw2");
+        assert_eq!(classic_tripcode("tripcode"), "3GqYIJ3Obs");
+        assert_eq!(classic_tripcode("test"), ".CzKQna1OU");
+        assert_eq!(classic_tripcode("pass word"), "WmqpBmd3xM");
+    }
+
+    #[test]
+    fn short_passwords_are_padded_for_salt() {
+        assert_eq!(classic_tripcode("a"), "ZnBI2EKkq.");
+        assert_eq!(classic_tripcode("!"), "KNs1o0VDv6");
+        assert_eq!(classic_tripcode("Ab"), "hs.ZXI6nrY");
+    }
+
+    #[test]
+    fn salt_characters_are_mapped_into_range() {
+        // ':' to '@' and '[' to '`' are shifted into letters
+        assert_eq!(classic_tripcode("a:b"), "5G6R5bcZ.A");
+        assert_eq!(classic_tripcode("q["), "wjOk8EsOi.");
+        // anything else outside '.' to 'z' becomes '.'
+        assert_eq!(classic_tripcode("x~"), "peic5uUf6I");
+        assert_eq!(classic_tripcode("ab~"), "tsZnYA.fR6");
+        assert_eq!(classic_tripcode("~~~"), "zQsh8Pcemk");
+    }
+
+    #[test]
+    fn passwords_are_escaped_before_hashing() {
+        assert_eq!(classic_tripcode("a&b"), "vbZwEe8/SY");
+        assert_eq!(classic_tripcode("<3"), "0JTVzlbXog");
+    }
+
+    #[test]
+    fn secure_tripcodes_depend_on_salt() {
+        assert_eq!(secure_tripcode("secret", "testsalt"), "xhkBaGkDpkx2AuM");
+        assert_eq!(secure_tripcode("secret", "othersalt"), "OJiQ5xHCMGQs/PR");
+    }
+
+    #[test]
+    fn author_names_are_split() {
+        assert_eq!(split_author("name", "s"), ("name", String::new()));
+        assert_eq!(split_author("name#", "s"), ("name", String::new()));
+        assert_eq!(split_author("name##", "s"), ("name", String::new()));
+        assert_eq!(
+            split_author("#faggot", "s"),
+            ("", "!Ep8pui8Vw2".to_string())
+        );
+        assert_eq!(
+            split_author("name##secret", "testsalt"),
+            ("name", "!!xhkBaGkDpkx2AuM".to_string())
+        );
+    }
+}
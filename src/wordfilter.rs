@@ -0,0 +1,93 @@
+//! Wordfilters that rewrite matched text instead of rejecting the post, stored in `data/wordfilters.yaml`.
+//! Rules are applied in the order they are listed, so later rules see the output of earlier ones.
+//! Store rules change author names and messages before they are saved, render rules change
+//! formatted message HTML every time it is shown, so they can be changed or removed later.
+
+use crate::rule_file::{compile_pattern, default_fields, PostField, RuleFile, WatchedFile};
+use regex::Regex;
+use serde::Deserialize;
+
+/// Default location of the wordfilter list
+pub const WORDFILTERS_PATH: &str = "./data/wordfilters.yaml";
+
+/// When a wordfilter rule is applied
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WordfilterStage {
+    /// Before the post is saved, on plain text with HTML tags removed
+    #[default]
+    Store,
+    /// When the message is shown, on formatted HTML
+    Render,
+}
+
+/// Single rewriting rule. Replacement can refer to capture groups of the pattern (`$1`, `${name}`).
+/// Empty `boards` means that it applies to every board
+#[derive(Deserialize, Clone, Debug)]
+pub struct WordfilterRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub replacement: String,
+    #[serde(default)]
+    pub stage: WordfilterStage,
+    /// Parts of the post the rule is applied to. Render rules only apply to messages
+    #[serde(default = "default_fields")]
+    pub scope: Vec<PostField>,
+    #[serde(default)]
+    pub boards: Vec<String>,
+}
+
+impl WordfilterRule {
+    fn applies_to(&self, board: &str, stage: WordfilterStage, scope: PostField) -> bool {
+        self.stage == stage
+            && self.scope.contains(&scope)
+            && (self.boards.is_empty() || self.boards.iter().any(|b| b == board))
+    }
+}
+
+/// Wordfilter rules along with their compiled patterns, in file order
+pub struct Wordfilters {
+    pub rules: Vec<(WordfilterRule, Regex)>,
+}
+
+impl RuleFile for Wordfilters {
+    type Rule = WordfilterRule;
+
+    const NAME: &'static str = "wordfilters";
+
+    fn compile(rules: Vec<WordfilterRule>) -> Result<Wordfilters, regex::Error> {
+        let rules = rules
+            .into_iter()
+            .map(|r| compile_pattern(&r.pattern).map(|re| (r, re)))
+            .collect::<Result<_, _>>()?;
+        Ok(Wordfilters { rules })
+    }
+
+    fn len(&self) -> usize {
+        self.rules.len()
+    }
+}
+
+impl Wordfilters {
+    /// Applies every matching rule of a stage to a part of a post on a board, in order
+    pub fn apply(
+        &self,
+        board: &str,
+        stage: WordfilterStage,
+        scope: PostField,
+        text: &str,
+    ) -> String {
+        let mut result = text.to_string();
+        for (rule, re) in &self.rules {
+            if rule.applies_to(board, stage, scope) {
+                result = re
+                    .replace_all(&result, rule.replacement.as_str())
+                    .into_owned();
+            }
+        }
+        result
+    }
+}
+
+/// Wordfilters shared between request handlers and the formatter, along with the file they were loaded from
+pub type SharedWordfilters = WatchedFile<Wordfilters>;